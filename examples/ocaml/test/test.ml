@@ -70,4 +70,88 @@ let () =
   let b = Array.init 10 (fun i -> if i mod 2 = 0 then 1 else 0) in
   let arr = Array_bool_1d.of_array ctx [| Array.length b |] b in
   let n = count_true ctx arr in
-  assert (n = Int64.of_int @@ Array.fold_left (+) 0 b)
+  assert (n = Int64.of_int @@ Array.fold_left (+) 0 b);
+
+  (* no_outputs, no_inputs *)
+  let arr = Array_f32_1d.of_array ctx [| 3 |] [| 1.0; 2.0; 3.0 |] in
+  let () = no_outputs ctx arr in
+  let out = no_inputs ctx in
+  assert (Array_i64_1d.get_array1 out = Array1.of_array Int64 C_layout [| 0L; 1L; 2L; 3L; 4L |]);
+
+  (* xor_bools *)
+  let data = [| true; false; false; true; true |] in
+  let arr = Array_bool_1d.of_bool_array ctx [| Array.length data |] data in
+  assert (Array_bool_1d.get_bool_array arr = data);
+  let out = xor_bools ctx arr true in
+  assert (Array_bool_1d.get_bool_array out = [| false; true; true; false; false |]);
+
+  (* scale5 *)
+  let dims = [| 2; 3; 1; 4; 2 |] in
+  let data = Array.init 48 Float.of_int in
+  let arr = Array_f32_5d.of_array ctx dims data in
+  let out = scale5 ctx arr in
+  assert (Array_f32_5d.shape out = dims);
+  let out' = Array_f32_5d.get_array1 out in
+  Array.iteri (fun i x -> assert (out'.{i} = x *. 2.0)) data;
+
+  (* transpose_f32 *)
+  let arr = Array_f32_2d.of_array ctx [| 3; 5 |] (Array.init 15 Float.of_int) in
+  let out = transpose_f32 ctx arr in
+  assert (Array_f32_2d.shape arr = [| 3; 5 |]);
+  assert (Array_f32_2d.shape out = [| 5; 3 |]);
+
+  (* store, restore *)
+  let arr = Array_f32_1d.of_array ctx [| 2 |] [| 1.0; 2.0 |] in
+  let tup = Tup.v ctx (Number.v ctx 2.0) arr in
+  let b = Tup.store tup in
+  let tup' = Tup.restore ctx b in
+  let out = Array_f32_1d.get_array1 (tup_mul ctx tup') in
+  assert (out = Array1.of_array Float32 C_layout [| 2.0; 4.0 |]);
+  (try
+    let _ = Tup.restore ctx (Bytes.sub b 0 (Bytes.length b - 1)) in
+    assert false
+  with Error InvalidData -> ());
+  (try
+    let _ = Tup.restore ctx Bytes.empty in
+    assert false
+  with Error InvalidData -> ());
+  (* Whole values, but not the ones making up a [Tup] *)
+  let number_bytes = Number.store (Number.v ctx 2.0) in
+  (try
+    let _ = Tup.restore ctx number_bytes in
+    assert false
+  with Error InvalidData -> ());
+  (try
+    let _ = Number.restore ctx b in
+    assert false
+  with Error InvalidData -> ());
+  let _ = Number.restore ctx number_bytes in
+
+  (* double_and_sign *)
+  let arr = Array_f32_1d.of_array ctx [| 3 |] [| 1.0; -2.0; 3.0 |] in
+  let (doubled, n, positive) = double_and_sign ctx arr in
+  assert (Array_f32_1d.get_array1 doubled = Array1.of_array Float32 C_layout [| 2.0; -4.0; 6.0 |]);
+  assert (n = 3L);
+  assert (Array_bool_1d.get_bool_array positive = [| true; false; true |]);
+
+  (* id_f16, values are converted to and from f16 *)
+  List.iter (fun x -> assert (id_f16 ctx x = x)) [ 0.5; -2.0; 65504.0; 0.0 ];
+  assert (id_f16 ctx 0.1 = 0.0999755859375);
+
+  (* f16 record field *)
+  let a = Weighted.v ctx 0.5 3.0 in
+  assert (Weighted.get_w a = 0.5);
+  assert (weigh ctx a = 1.5);
+
+  (* of_array checks the shape *)
+  (try
+    let _ = Array_f32_2d.of_array ctx [| 3; 3 |] (Array.make 6 0.0) in
+    assert false
+  with Error (InvalidShape (9, 6)) -> ());
+  (try
+    let _ = Array_f32_2d.of_array ctx [| 6 |] (Array.make 6 0.0) in
+    assert false
+  with Error (InvalidShape (2, 1)) -> ());
+
+  (* struct is a keyword, the function is named struct_ *)
+  assert (struct_ ctx 2L = 6L)
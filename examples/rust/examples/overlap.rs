@@ -0,0 +1,53 @@
+// Compares calling an entry point and preparing the next input one after the other with
+// overlapping the two using the `_async` variant, run with e.g.
+// `FUTHARK_BACKEND=opencl cargo run --release --example overlap`
+//
+// Only backends that run the program asynchronously, `opencl`, `cuda` and `hip`, can benefit.
+// With `c` and `multicore` the entry point has finished when it returns, so both loops take
+// about as long and no overlap can be shown
+
+use futhark_bindgen_example::*;
+use std::time::Instant;
+
+const N: usize = 1000;
+const BATCHES: usize = 20;
+
+/// Host side work done for every batch
+fn prepare(batch: usize) -> Vec<f64> {
+    (0..N * N)
+        .map(|i| ((i + batch) as f64).sin().abs().sqrt())
+        .collect()
+}
+
+fn main() -> Result<(), Error> {
+    // With auto sync enabled creating the next input would wait for the pending entry point
+    let ctx = Context::builder().auto_sync(false).build()?;
+
+    let start = Instant::now();
+    for batch in 0..BATCHES {
+        let data = prepare(batch);
+        let input = ArrayF64D2::new(&ctx, [N as i64, N as i64], &data)?;
+        ctx.mul2(&input)?;
+        ctx.sync()?;
+    }
+    println!("sequential: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let mut pending: std::option::Option<PendingResults<ArrayF64D2>> = None;
+    let mut data = prepare(0);
+    for batch in 0..BATCHES {
+        let input = ArrayF64D2::new(&ctx, [N as i64, N as i64], &data)?;
+        if let Some(p) = pending.take() {
+            p.wait()?;
+        }
+        pending = Some(ctx.mul2_async(&input)?);
+        // Prepare the next batch while Futhark works on this one
+        data = prepare(batch + 1);
+    }
+    if let Some(p) = pending {
+        p.wait()?;
+    }
+    println!("overlapped: {:?}", start.elapsed());
+
+    Ok(())
+}
@@ -37,4 +37,436 @@ mod tests {
         let n = ctx.count_lines(&data).unwrap();
         assert_eq!(n, 4);
     }
+
+    #[test]
+    fn scale_f16() {
+        let ctx = Context::new_with_options(Options::new().debug().log().profile()).unwrap();
+
+        let data: Vec<f32> = (0..16).map(|x| x as f32 * 0.25).collect();
+        let data16: Vec<_> = data.iter().map(|x| half::f16::from_f32(*x)).collect();
+        let arr = ArrayF16D1::new(&ctx, [data16.len() as i64], &data16).unwrap();
+        let out = ctx.scale_f16(&arr, half::f16::from_f32(1.5)).unwrap();
+        let out = out.get().unwrap();
+
+        for (x, y) in data.iter().zip(out) {
+            assert!((x * 1.5 - y.to_f32()).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn array_of_tuples() {
+        let ctx = Context::new_with_options(Options::new().debug().log().profile()).unwrap();
+
+        let a = ArrayF32D1::new(&ctx, [3], [1.0, 2.0, 3.0]).unwrap();
+        let b = ArrayI64D1::new(&ctx, [3], [4, 5, 6]).unwrap();
+        let pairs = Arr_pair_1d::new(&ctx, &a, &b).unwrap();
+        let out = ctx.scale_pairs(&pairs).unwrap();
+        assert_eq!(out.len(), 3);
+
+        let second = out.get(1).unwrap();
+        assert_eq!(second.get_0().unwrap(), 10.0);
+        assert_eq!(second.get_1().unwrap(), 6);
+        assert_eq!(out.get_0().unwrap().get().unwrap(), vec![4.0, 10.0, 18.0]);
+        assert_eq!(out.get_1().unwrap().get().unwrap(), vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn no_inputs_or_outputs() {
+        let ctx = Context::new_with_options(Options::new().debug().log().profile()).unwrap();
+
+        let arr = ArrayF32D1::new(&ctx, [3], [1.0, 2.0, 3.0]).unwrap();
+        let () = ctx.no_outputs(&arr).unwrap();
+
+        let out = ctx.no_inputs().unwrap();
+        assert_eq!(out.get().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn consume() {
+        let ctx = Context::new_with_options(Options::new().debug().log().profile()).unwrap();
+
+        let arr = ArrayF32D1::new(&ctx, [3], [1.0, 2.0, 3.0]).unwrap();
+        let out = ctx.add_one_in_place(arr).unwrap();
+        assert_eq!(out.get().unwrap(), vec![2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn bool_round_trip() {
+        let ctx = Context::new_with_options(Options::new().debug().log().profile()).unwrap();
+
+        let data = [true, false, false, true, true];
+        let arr = ArrayBoolD1::new(&ctx, [data.len() as i64], data).unwrap();
+        assert_eq!(arr.get().unwrap(), data);
+
+        let out = ctx.xor_bools(&arr, true).unwrap();
+        assert_eq!(out.get().unwrap(), [false, true, true, false, false]);
+    }
+
+    #[test]
+    fn rank5() {
+        let ctx = Context::new_with_options(Options::new().debug().log().profile()).unwrap();
+
+        let dims = [2, 3, 1, 4, 2];
+        let data: Vec<f32> = (0..48).map(|x| x as f32).collect();
+        let arr = ArrayF32D5::new(&ctx, dims, &data).unwrap();
+        let out = ctx.scale5(&arr).unwrap();
+        assert_eq!(out.shape(), &dims);
+        let expected: Vec<f32> = data.iter().map(|x| x * 2.0).collect();
+        assert_eq!(out.get().unwrap(), expected);
+    }
+
+    #[test]
+    fn builder() {
+        let ctx = Context::builder()
+            .debug()
+            .auto_sync(false)
+            .cache_in(std::env::temp_dir())
+            .build()
+            .unwrap();
+        let arr = ArrayF32D1::new(&ctx, [3], &[1.0, 2.0, 3.0]).unwrap();
+        let out = ctx.add_one_in_place(arr).unwrap();
+        ctx.sync().unwrap();
+        assert_eq!(out.get().unwrap(), [2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn profiling() {
+        let ctx = Context::builder().profiling(true).build().unwrap();
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+
+        ctx.pause_profiling();
+        let arr = ArrayI64D1::new(&ctx, [8], &data).unwrap();
+        assert_eq!(ctx.binary_search(&arr, 4).unwrap(), 3);
+
+        ctx.unpause_profiling();
+        for _ in 0..100 {
+            ctx.binary_search(&arr, 4).unwrap();
+        }
+        ctx.pause_profiling();
+        assert!(!ctx.report().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_tuning_param() {
+        let err = Options::new().set_tuning_param("bogus", 1).unwrap_err();
+        assert!(matches!(err, Error::UnknownTuningParam(name) if name == "bogus"));
+
+        let path = std::env::temp_dir().join("futhark-bindgen-example.tuning");
+        std::fs::write(&path, "bogus=1\n").unwrap();
+        let ctx = Options::new().load_tuning_file(&path).unwrap().build();
+        assert!(ctx.is_ok());
+
+        std::fs::write(&path, "bogus\n").unwrap();
+        let err = Options::new().load_tuning_file(&path).unwrap_err();
+        assert!(matches!(err, Error::InvalidTuningFile(_)));
+
+        let err = Options::new().load_tuning_file(path.with_extension("missing")).unwrap_err();
+        assert!(matches!(err, Error::Io(_)));
+    }
+
+    #[test]
+    fn errors() {
+        let ctx = Context::new().unwrap();
+        let err = ArrayF32D1::new(&ctx, [4], &[1.0, 2.0, 3.0]).unwrap_err();
+        assert!(matches!(err, Error::InvalidShape { expected: 4, got: 3 }));
+
+        let err = Options::new().cache_file("bad\0path").build().err().unwrap();
+        assert!(matches!(err, Error::Nul(_)));
+    }
+
+    #[test]
+    fn values_into() {
+        let ctx = Context::new().unwrap();
+        let mut buf = vec![0.0; 3];
+
+        let arr = ArrayF32D1::new(&ctx, [3], &[1.0, 2.0, 3.0]).unwrap();
+        assert_eq!(arr.element_count(), buf.len());
+        arr.values_into(&mut buf).unwrap();
+        assert_eq!(buf, [1.0, 2.0, 3.0]);
+
+        let arr = ArrayF32D1::new(&ctx, [3], &[4.0, 5.0, 6.0]).unwrap();
+        arr.values_into(&mut buf).unwrap();
+        assert_eq!(buf, [4.0, 5.0, 6.0]);
+        assert_eq!(arr.values_vec().unwrap(), buf);
+
+        let err = arr.values_into(&mut buf[..2]).unwrap_err();
+        assert!(matches!(err, Error::InvalidShape { expected: 3, got: 2 }));
+    }
+
+    #[test]
+    fn invalid_dims() {
+        let ctx = Context::new().unwrap();
+        let err = ArrayF64D2::new(&ctx, [i64::MAX, 2], &[1.0]).unwrap_err();
+        assert!(matches!(err, Error::InvalidDims(_)));
+
+        let err = ArrayF64D2::new(&ctx, [-1, -1], &[1.0]).unwrap_err();
+        assert!(matches!(err, Error::InvalidDims(_)));
+
+        let arr = ArrayF32D1::from_slice(&ctx, &[1.0, 2.0]).unwrap();
+        assert_eq!(arr.shape(), &[2]);
+    }
+
+    #[test]
+    fn transpose() {
+        let ctx = Context::new().unwrap();
+        let data: Vec<f32> = (0..15).map(|x| x as f32).collect();
+        let arr = ArrayF32D2::new(&ctx, [3, 5], &data).unwrap();
+        let out = ctx.transpose_f32(&arr).unwrap();
+        assert_eq!(arr.shape(), &[3, 5]);
+        assert_eq!(out.shape(), &[5, 3]);
+        assert_eq!(out.len(), 5);
+        assert_eq!(out.element_count(), 15);
+    }
+
+    #[test]
+    fn as_slice() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF32D1::new(&ctx, [3], &[1.0, 2.0, 3.0]).unwrap();
+        let out = ctx.add_one_in_place(arr).unwrap();
+        assert_eq!(out.as_slice().unwrap(), &[2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn threads() {
+        let ctx = Context::new().unwrap();
+        let data: Vec<i64> = (0..1000).collect();
+        let arr = ArrayI64D1::new(&ctx, [data.len() as i64], &data).unwrap();
+
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let (ctx, arr) = (&ctx, &arr);
+                s.spawn(move || {
+                    for i in 0..100 {
+                        let x = t * 100 + i;
+                        assert_eq!(ctx.binary_search(arr, x).unwrap(), x);
+                        let out = ArrayI64D1::new(ctx, [1], &[x]).unwrap();
+                        assert_eq!(out.get().unwrap(), [x]);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn drop_context_first() {
+        let arr = {
+            let ctx = Context::new().unwrap();
+            ArrayF32D1::new(&ctx, [2], &[1.0, 2.0]).unwrap()
+        };
+        assert_eq!(arr.get().unwrap(), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn try_clone() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF32D1::new(&ctx, [3], &[1.0, 2.0, 3.0]).unwrap();
+        let copy = arr.try_clone().unwrap();
+        let out = ctx.add_one_in_place(arr).unwrap();
+        assert_eq!(out.get().unwrap(), [2.0, 3.0, 4.0]);
+        assert_eq!(copy.get().unwrap(), [1.0, 2.0, 3.0]);
+
+        let arr = ArrayF32D1::new(&ctx, [2], &[1.0, 2.0]).unwrap();
+        let number = Number::new(&ctx, 2.0).unwrap();
+        let t = Tup::new(&ctx, &number, &arr).unwrap();
+        let t = t.try_clone().unwrap();
+        assert_eq!(ctx.tup_mul(&t).unwrap().get().unwrap(), [2.0, 4.0]);
+    }
+
+    #[test]
+    fn futhark_data() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF32D2::new(&ctx, [2, 2], &[1.0, 2.0, 3.0, 4.0]).unwrap();
+        let empty = ArrayF32D2::new(&ctx, [0, 3], &[]).unwrap();
+        let mut buf = Vec::new();
+        arr.write_futhark_data(&mut buf).unwrap();
+        empty.write_futhark_data(&mut buf).unwrap();
+        futhark_data::write_scalar(&mut buf, 5i64).unwrap();
+
+        let mut reader = buf.as_slice();
+        let out = ArrayF32D2::read_futhark_data(&ctx, &mut reader).unwrap();
+        assert_eq!(out.shape(), &[2, 2]);
+        assert_eq!(out.get().unwrap(), [1.0, 2.0, 3.0, 4.0]);
+        let out = ArrayF32D2::read_futhark_data(&ctx, &mut reader).unwrap();
+        assert_eq!(out.shape(), &[0, 3]);
+        assert!(ArrayF32D1::read_futhark_data(&ctx, &mut reader).is_err());
+    }
+
+    #[test]
+    fn store_restore() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF32D1::new(&ctx, [2], &[1.0, 2.0]).unwrap();
+        let number = Number::new(&ctx, 2.0).unwrap();
+        let t = Tup::new(&ctx, &number, &arr).unwrap();
+        let bytes = t.store().unwrap();
+        let t = Tup::restore(&ctx, &bytes).unwrap();
+        assert_eq!(ctx.tup_mul(&t).unwrap().get().unwrap(), [2.0, 4.0]);
+
+        assert!(Tup::restore(&ctx, &bytes[..bytes.len() - 1]).is_err());
+        assert!(Tup::restore(&ctx, b"garbage").is_err());
+        assert!(Tup::restore(&ctx, &[]).is_err());
+
+        // Whole values, but not the ones making up a `Tup`
+        let number_bytes = number.store().unwrap();
+        assert!(Tup::restore(&ctx, &number_bytes).is_err());
+        assert!(Tup::restore(&ctx, &bytes[..number_bytes.len()]).is_err());
+        assert!(Number::restore(&ctx, &bytes).is_err());
+        assert!(Number::restore(&ctx, &number_bytes).is_ok());
+    }
+
+    #[test]
+    fn project_twice() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF32D1::new(&ctx, [3], &[1.0, 2.0, 3.0]).unwrap();
+        let number = Number::new(&ctx, 2.0).unwrap();
+        let t = Tup::new(&ctx, &number, &arr).unwrap();
+        drop((arr, number));
+
+        let a = t.get_1().unwrap();
+        let b = t.get_1().unwrap();
+        drop(a);
+        assert_eq!(b.get().unwrap(), [1.0, 2.0, 3.0]);
+        drop(b);
+        assert_eq!(t.get_1().unwrap().get().unwrap(), [1.0, 2.0, 3.0]);
+        assert_eq!(t.get_0().unwrap().get_x().unwrap(), 2.0);
+    }
+
+    #[test]
+    fn sum_type() {
+        let ctx = Context::new().unwrap();
+        let some = Option::new_some(&ctx, 5).unwrap();
+        assert_eq!(some.variant(), OptionVariant::Some);
+        assert!(matches!(some.get_none(), Err(Error::InvalidVariant)));
+        let some = some.into_none().unwrap_err();
+        assert_eq!(some.into_some().ok(), Some(5));
+
+        let none = Option::new_none(&ctx).unwrap();
+        assert!(ctx.is_none(&none).unwrap());
+        assert!(none.into_none().is_ok());
+    }
+
+    #[test]
+    fn batch() {
+        let ctx = Context::new().unwrap();
+        let data = &[1, 2, 3, 4, 5, 7, 8];
+        let arr = ArrayI64D1::new(&ctx, [data.len() as i64], data).unwrap();
+        let results = ctx
+            .batch(|b| {
+                for x in [8, 1, 6] {
+                    b.binary_search(&arr, x)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        let indices: Vec<_> = results
+            .into_iter()
+            .map(|r| match r {
+                BatchResult::BinarySearch(i) => i,
+                _ => panic!("wrong entry point"),
+            })
+            .collect();
+        assert_eq!(indices, [6, 0, 5]);
+
+        let pending = ctx.binary_search_async(&arr, 4).unwrap();
+        assert_eq!(pending.wait().unwrap(), 3);
+    }
+
+    #[test]
+    fn multiple_outputs() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF32D1::new(&ctx, [3], &[1.0, -2.0, 3.0]).unwrap();
+        let (doubled, n, positive): (ArrayF32D1, i64, ArrayBoolD1) =
+            ctx.double_and_sign(&arr).unwrap();
+        assert_eq!(doubled.get().unwrap(), [2.0, -4.0, 6.0]);
+        assert_eq!(n, 3);
+        assert_eq!(positive.get().unwrap(), [true, false, true]);
+    }
+
+    #[test]
+    fn f16_scalar() {
+        let ctx = Context::new().unwrap();
+        for x in [0.5, -2.0, 65504.0, 0.0] {
+            let y = ctx.id_f16(half::f16::from_f32(x)).unwrap();
+            assert_eq!(y.to_f32(), x);
+        }
+        let y = ctx.id_f16(half::f16::from_f32(0.1)).unwrap();
+        assert_eq!(y.to_f32(), 0.0999755859375);
+    }
+
+    #[test]
+    fn f16_record_field() {
+        let ctx = Context::new().unwrap();
+        let a = Weighted::new(&ctx, half::f16::from_f32(0.5), 3.0).unwrap();
+        assert_eq!(a.get_w().unwrap().to_f32(), 0.5);
+        assert_eq!(ctx.weigh(&a).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn usize_dims() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF32D2::new_usize_dims(&ctx, [2, 3], [0.0; 6]).unwrap();
+        assert_eq!(arr.shape(), &[2, 3]);
+        let err = ArrayF32D2::new_usize_dims(&ctx, [3, 3], [0.0; 6]).unwrap_err();
+        assert!(matches!(err, Error::InvalidShape { expected: 9, got: 6 }));
+        let err = ArrayF32D2::new_usize_dims(&ctx, [usize::MAX, 0], [0.0; 0]).unwrap_err();
+        assert!(matches!(err, Error::InvalidDims(_)));
+    }
+
+    #[test]
+    fn keyword_entry() {
+        let ctx = Context::new().unwrap();
+        assert_eq!(ctx.r#struct(2).unwrap(), 6);
+    }
+
+    #[test]
+    fn debug_and_host() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF32D2::new(&ctx, [2, 3], [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_eq!(format!("{arr:?}"), "ArrayF32D2 { shape: [2, 3] }");
+        assert_eq!(
+            arr.to_host().unwrap(),
+            (vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], [2, 3])
+        );
+
+        let out = ctx.transpose_f32(&ctx.transpose_f32(&arr).unwrap()).unwrap();
+        out.assert_approx_eq(&arr, 0.0);
+        let close = ArrayF32D2::new(&ctx, [2, 3], [1.0, 2.0, 3.0, 4.0, 5.0, 6.001]).unwrap();
+        close.assert_approx_eq(&arr, 0.01);
+        let far = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            close.assert_approx_eq(&arr, 0.0001)
+        }));
+        assert!(far.is_err());
+
+        let number = Number::new(&ctx, 2.5).unwrap();
+        assert!(format!("{number:?}").starts_with("Number(0x"));
+    }
+
+    #[test]
+    fn memory_usage() {
+        let ctx = Context::new().unwrap();
+        let arr = ArrayF64D2::new(&ctx, [100, 100], vec![1.0; 10000]).unwrap();
+        drop(ctx.mul2(&arr).unwrap());
+        assert!(!ctx.peak_memory_usage().unwrap().is_empty());
+        ctx.clear_caches().unwrap();
+    }
+
+    #[test]
+    fn parse_memory_report() {
+        let json = r#"{"memory":{"default":1024,"device":2048},"events":[]}"#;
+        let text = "Peak memory usage for default space: 1024 bytes.\n\
+            Peak memory usage for space 'device': 2048 bytes.\n";
+        for report in [json, text] {
+            let usage = parse_peak_memory(report);
+            assert_eq!(usage.len(), 2);
+            assert_eq!(usage["default"], 1024);
+            assert_eq!(usage["device"], 2048);
+        }
+        assert!(parse_peak_memory("").is_empty());
+    }
+
+    #[test]
+    fn provenance() {
+        assert!(FUTHARK_COMPILER_VERSION.starts_with("Futhark "));
+        assert_eq!(FUTHARK_SOURCE_SHA256.len(), 64);
+        assert!(!FUTHARK_BACKEND.is_empty());
+    }
 }
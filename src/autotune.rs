@@ -0,0 +1,36 @@
+/// Options passed to `futhark autotune`, see `Compiler::with_autotune`
+#[derive(Debug, Clone, Default, Hash)]
+pub struct AutotuneOptions {
+    runs: Option<u32>,
+    timeout: Option<u32>,
+}
+
+impl AutotuneOptions {
+    /// Create a new `AutotuneOptions` with the default settings
+    pub fn new() -> AutotuneOptions {
+        AutotuneOptions::default()
+    }
+
+    /// Number of runs for each dataset: `--runs`
+    pub fn with_runs(mut self, runs: u32) -> Self {
+        self.runs = Some(runs);
+        self
+    }
+
+    /// Initial tuning timeout for each dataset in seconds: `--timeout`
+    pub fn with_timeout(mut self, seconds: u32) -> Self {
+        self.timeout = Some(seconds);
+        self
+    }
+
+    pub(crate) fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(runs) = self.runs {
+            args.push(format!("--runs={runs}"));
+        }
+        if let Some(timeout) = self.timeout {
+            args.push(format!("--timeout={timeout}"));
+        }
+        args
+    }
+}
@@ -3,10 +3,7 @@ use futhark_bindgen::*;
 use argh::FromArgs;
 
 fn parse_backend(s: &str) -> Result<Backend, String> {
-    let mut s = s.to_string();
-    s.make_ascii_lowercase();
-    let x = serde_json::from_str(&format!("\"{}\"", s)).expect("Invalid backend");
-    Ok(x)
+    s.parse().map_err(|e: Error| e.to_string())
 }
 
 #[derive(Debug, FromArgs)]
@@ -29,13 +26,12 @@ enum Commands {
     description = "List libraries for the selected backend",
     subcommand
 )]
-
 struct Libs {
     #[argh(
         option,
         default = "Backend::C",
         from_str_fn(parse_backend),
-        description = "futhark backend: c, cuda, opencl, multicore"
+        description = "futhark backend: c, cuda, opencl, multicore, ispc, hip"
     )]
     backend: Backend,
 }
@@ -43,7 +39,10 @@ struct Libs {
 #[derive(Debug, FromArgs)]
 #[argh(name = "run", description = "generate bindings", subcommand)]
 struct Run {
-    #[argh(positional, description = "futhark input file")]
+    #[argh(
+        positional,
+        description = "futhark input file, or the manifest of a previous compilation to regenerate bindings without running the futhark compiler"
+    )]
     input: std::path::PathBuf,
 
     #[argh(positional, description = "output file")]
@@ -53,50 +52,101 @@ struct Run {
         option,
         default = "Backend::C",
         from_str_fn(parse_backend),
-        description = "futhark backend: c, cuda, opencl, multicore"
+        description = "futhark backend: c, cuda, opencl, multicore, ispc, hip"
     )]
     backend: Backend,
 
-    #[argh(option, description = "path to futhark compiler")]
-    compiler: Option<String>,
+    #[argh(
+        option,
+        description = "output language: rust or ocaml, detected from the output file extension by default"
+    )]
+    lang: Option<String>,
+
+    #[argh(option, description = "path to the futhark compiler")]
+    futhark_exe: Option<std::path::PathBuf>,
 
     #[argh(
         option,
-        long = "futhark-arg",
+        long = "extra-arg",
         short = 'f',
-        description = "arguments to be passed to the futhark compiler"
+        description = "argument to be passed to the futhark compiler, can be repeated"
+    )]
+    extra_args: Vec<String>,
+
+    /// Old name of `--futhark-exe`
+    #[argh(option, hidden_help, description = "same as --futhark-exe")]
+    compiler: Option<std::path::PathBuf>,
+
+    /// Old name of `--extra-arg`
+    #[argh(
+        option,
+        long = "futhark-arg",
+        hidden_help,
+        description = "same as --extra-arg"
     )]
     futhark_args: Vec<String>,
 }
 
-fn main() -> Result<(), Error> {
-    let args: Main = argh::from_env();
+/// Approximate target triple of the host, only the parts used by `Backend::required_libs`
+fn host_target() -> String {
+    let os = match std::env::consts::OS {
+        "macos" => "apple-darwin",
+        "windows" if cfg!(target_env = "msvc") => "pc-windows-msvc",
+        os => os,
+    };
+    format!("{}-{os}", std::env::consts::ARCH)
+}
 
-    match args.command {
-        Commands::Run(mut args) => {
-            if args.output.is_relative() {
-                args.output = std::path::PathBuf::from(".").join(args.output);
-            }
-            let out_dir = args.output.parent().unwrap().canonicalize().unwrap();
-            let mut compiler = Compiler::new(args.backend, &args.input)
-                .with_extra_args(args.futhark_args)
-                .with_output_dir(out_dir);
-            if let Some(exe) = args.compiler {
-                compiler = compiler.with_executable_name(exe);
-            }
-            let pkg = compiler.compile()?;
-            let mut config = Config::new(args.output)?;
-            let mut gen = config.detect().expect("Unable to detect output language");
-            gen.generate(&pkg, &mut config)?;
+fn run(args: Run) -> Result<(), Error> {
+    let pkg = if args.input.extension() == Some(std::ffi::OsStr::new("json")) {
+        Package::from_manifest(&args.input)?
+    } else {
+        let out_dir = match args.output.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+        let extra_args = args
+            .extra_args
+            .into_iter()
+            .chain(args.futhark_args)
+            .collect();
+        let mut compiler = Compiler::new(args.backend, &args.input)
+            .with_extra_args(extra_args)
+            .with_output_dir(out_dir);
+        if let Some(exe) = args.futhark_exe.or(args.compiler) {
+            compiler = compiler.with_executable_name(exe);
         }
+        compiler.compile()?
+    };
+
+    let mut config = Config::new(&args.output)?;
+    let gen = match &args.lang {
+        Some(lang) => config.language(lang),
+        None => config.detect(),
+    };
+    let mut gen = gen.ok_or_else(|| Error::UnsupportedOutputLanguage(args.output.clone()))?;
+    gen.generate(&pkg, &mut config)
+}
+
+fn main() {
+    let args: Main = argh::from_env();
+
+    let res = match args.command {
+        Commands::Run(args) => run(args),
         Commands::Libs(args) => {
-            args.backend
-                .required_c_libs()
-                .iter()
-                .for_each(|x| print!("-l{x} "));
+            for lib in args.backend.required_libs(&host_target()) {
+                match lib {
+                    RequiredLib::Lib(name) => print!("-l{name} "),
+                    RequiredLib::Framework(name) => print!("-framework {name} "),
+                }
+            }
             println!();
+            Ok(())
         }
-    }
+    };
 
-    Ok(())
+    if let Err(e) = res {
+        eprintln!("futhark-bindgen: {e}");
+        std::process::exit(1);
+    }
 }
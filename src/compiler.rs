@@ -3,81 +3,869 @@ use crate::*;
 /// Wrapper around the Futhark compiler
 #[derive(Debug, Clone)]
 pub struct Compiler {
-    exe: String,
+    exe: std::path::PathBuf,
+    ispc_exe: std::path::PathBuf,
     backend: Backend,
     src: std::path::PathBuf,
     extra_args: Vec<String>,
-    output_dir: std::path::PathBuf,
+    output_dir: Option<std::path::PathBuf>,
+    output_name: Option<String>,
+    force_rebuild: bool,
+    pkg_sync: bool,
+    fallback_backends: Vec<Backend>,
+    safe: bool,
+    verbosity: u8,
+    profile: bool,
+    entry_points: Vec<String>,
+    verbose: bool,
+    run_tests: bool,
+    autotune: Option<AutotuneOptions>,
+    warnings: bool,
+}
+
+/// Split compiler output into messages, each message starts on an unindented line and
+/// continues on the following indented lines
+fn compiler_messages(output: &str) -> impl Iterator<Item = String> + '_ {
+    let mut lines = output.lines().filter(|l| !l.trim().is_empty()).peekable();
+    std::iter::from_fn(move || {
+        let mut msg = lines.next()?.trim().to_string();
+        while let Some(line) = lines.next_if(|l| l.starts_with(char::is_whitespace)) {
+            msg.push(' ');
+            msg.push_str(line.trim());
+        }
+        Some(msg)
+    })
+}
+
+/// Check whether a test failure was caused by a missing GPU or driver rather than the program
+fn is_device_error(report: &str) -> bool {
+    const ERRORS: &[&str] = &[
+        "CUDA_ERROR_NO_DEVICE",
+        "CUDA_ERROR_NOT_INITIALIZED",
+        "cuInit",
+        "hipInit",
+        "No OpenCL platforms",
+        "No suitable OpenCL",
+        "CL_PLATFORM_NOT_FOUND_KHR",
+        "clGetPlatformIDs",
+    ];
+    ERRORS.iter().any(|e| report.contains(e))
+}
+
+/// Find an executable by checking each directory in `$PATH`
+fn find_executable(path: &std::path::Path) -> Option<std::path::PathBuf> {
+    if path.components().count() > 1 {
+        return Some(path.to_path_buf());
+    }
+    let dirs = std::env::var_os("PATH")?;
+    std::env::split_paths(&dirs)
+        .map(|dir| dir.join(path))
+        .find(|p| p.is_file())
 }
 
 impl Compiler {
     /// Create a new `Compiler` instance with the selected backend and Futhark source file
+    ///
+    /// This panics if `src` doesn't end in a file name, see `try_new`
     pub fn new(backend: Backend, src: impl AsRef<std::path::Path>) -> Compiler {
-        Compiler {
-            exe: String::from("futhark"),
+        Compiler::try_new(backend, src).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create a new `Compiler` instance, returning an error if `src` doesn't end in a file name
+    ///
+    /// The source file is not required to exist until `compile` is called
+    pub fn try_new(backend: Backend, src: impl AsRef<std::path::Path>) -> Result<Compiler, Error> {
+        if src.as_ref().file_stem().is_none() {
+            return Err(Error::InvalidSourceFile(src.as_ref().to_path_buf()));
+        }
+        Ok(Compiler {
+            exe: std::env::var_os("FUTHARK_EXE")
+                .map(std::path::PathBuf::from)
+                .unwrap_or_else(|| std::path::PathBuf::from("futhark")),
+            ispc_exe: std::path::PathBuf::from("ispc"),
             src: src.as_ref().to_path_buf(),
             extra_args: Vec::new(),
-            output_dir: src
-                .as_ref()
-                .canonicalize()
-                .unwrap()
-                .parent()
-                .unwrap()
-                .to_path_buf(),
+            output_dir: None,
+            output_name: None,
+            force_rebuild: false,
+            pkg_sync: false,
+            fallback_backends: Vec::new(),
+            safe: false,
+            verbosity: 0,
+            profile: false,
+            entry_points: Vec::new(),
+            verbose: false,
+            run_tests: false,
+            autotune: None,
+            warnings: true,
             backend,
-        }
+        })
     }
 
-    /// By default the executable name is set to `futhark`, this function can be
-    /// used to set a different name or path
-    pub fn with_executable_name(mut self, name: impl AsRef<str>) -> Self {
+    /// By default the executable name is set to the value of the `FUTHARK_EXE` environment
+    /// variable, or `futhark` if it isn't set, this function can be used to set a different
+    /// name or path
+    pub fn with_executable_name(mut self, name: impl AsRef<std::path::Path>) -> Self {
         self.exe = name.as_ref().into();
         self
     }
 
+    /// By default the ISPC executable name is set to `ispc`, this function can be
+    /// used to set a different name or path
+    ///
+    /// This is only used when linking packages compiled with `Backend::ISPC`
+    pub fn with_ispc_executable_name(mut self, name: impl AsRef<std::path::Path>) -> Self {
+        self.ispc_exe = name.as_ref().into();
+        self
+    }
+
     /// Supply additional arguments to be passed to the `futhark` executable
     pub fn with_extra_args(mut self, args: Vec<String>) -> Self {
         self.extra_args = args;
         self
     }
 
-    /// Set the output directory where the C files and manifest will be created
+    /// Ignore `unsafe` in the Futhark program and always perform bounds checks: `--safe`
+    pub fn with_safe(mut self, safe: bool) -> Self {
+        self.safe = safe;
+        self
+    }
+
+    /// Set the verbosity of the Futhark compiler, each level adds a `-v` flag
+    pub fn with_verbosity(mut self, level: u8) -> Self {
+        self.verbosity = level;
+        self
+    }
+
+    /// Enable profiling support in the generated code: `--profile`
+    pub fn with_profile(mut self, profile: bool) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Treat the given functions as additional entry points: `--entry-point`
+    pub fn with_entry_points(mut self, names: &[&str]) -> Self {
+        self.entry_points = names.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Print the commands used to compile and link the package, using `cargo:warning`
+    /// when running inside of a build script
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Forward the warnings printed by the Futhark compiler using `cargo:warning`, this is
+    /// enabled by default. When disabled they are only written to stderr
+    pub fn with_warnings(mut self, warnings: bool) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// Run `futhark test` with the selected backend after compiling, failing tests result in
+    /// `Error::FutharkTestsFailed`. This is disabled by default
+    ///
+    /// The tests are skipped with a warning if the backend is unable to find a device
+    pub fn with_run_tests(mut self, run_tests: bool) -> Self {
+        self.run_tests = run_tests;
+        self
+    }
+
+    /// Run `futhark autotune` after compiling, the resulting tuning parameters are embedded
+    /// in the generated Rust code and applied when creating a `Context`
+    ///
+    /// Like compilation, autotuning is skipped when the program is unchanged
+    pub fn with_autotune(mut self, options: AutotuneOptions) -> Self {
+        self.autotune = Some(options);
+        self
+    }
+
+    /// Set the output directory where the C files and manifest will be created, it is
+    /// created if it doesn't exist
+    ///
+    /// By default this is the directory containing the source file
     pub fn with_output_dir(mut self, dir: impl AsRef<std::path::Path>) -> Self {
-        self.output_dir = dir.as_ref().to_path_buf();
+        self.output_dir = Some(dir.as_ref().to_path_buf());
         self
     }
 
+    /// Set the file name, without extension, of the C files and manifest, by default
+    /// the name of the source file is used
+    ///
+    /// The name of the static library created by `Package::link` is derived from this
+    pub fn with_output_name(mut self, name: impl AsRef<str>) -> Self {
+        self.output_name = Some(name.as_ref().to_string());
+        self
+    }
+
+    /// Always run the Futhark compiler, even if the outputs from a previous
+    /// compilation are still up to date
+    pub fn with_force_rebuild(mut self, force: bool) -> Self {
+        self.force_rebuild = force;
+        self
+    }
+
+    /// Run `futhark pkg sync` before compiling if there is a `futhark.pkg` file
+    /// in the same directory as the source file
+    pub fn with_pkg_sync(mut self, sync: bool) -> Self {
+        self.pkg_sync = sync;
+        self
+    }
+
+    /// Backends to try, in order, when the SDK needed by the selected backend can't be found,
+    /// see `FUTHARK_BINDGEN_CUDA_LIB_DIR`, `CUDA_PATH`, `OPENCL_ROOT` and the system include
+    /// directories. Errors from the Futhark compiler are returned without trying other backends,
+    /// since it only generates C code and fails the same way with every backend
+    pub fn with_fallback_backends(mut self, backends: &[Backend]) -> Self {
+        self.fallback_backends = backends.to_vec();
+        self
+    }
+
+    fn pkg_sync(&self) -> Result<(), Error> {
+        let dir = package::source_dir(&self.src);
+        if !dir.join("futhark.pkg").exists() {
+            return Ok(());
+        }
+
+        let out = std::process::Command::new(&self.exe)
+            .args(["pkg", "sync"])
+            .current_dir(dir)
+            .output()
+            .map_err(|e| self.spawn_error(e))?;
+        if !out.status.success() {
+            return Err(Error::PkgSyncFailed {
+                stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+                exit_code: out.status.code(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Options passed to the Futhark compiler, the typed options are added after
+    /// `extra_args` so they take precedence
+    fn options(&self) -> Vec<String> {
+        let mut args = self.extra_args.clone();
+        if self.safe {
+            args.push("--safe".to_string());
+        }
+        for _ in 0..self.verbosity {
+            args.push("-v".to_string());
+        }
+        if self.profile {
+            args.push("--profile".to_string());
+        }
+        for name in &self.entry_points {
+            args.push(format!("--entry-point={name}"));
+        }
+        args
+    }
+
+    /// Convert an error from running the Futhark compiler
+    fn spawn_error(&self, e: std::io::Error) -> Error {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            Error::ExecutableNotFound {
+                exe: self.exe.display().to_string(),
+            }
+        } else {
+            Error::Io(e)
+        }
+    }
+
+    fn version_string(&self) -> Result<String, Error> {
+        let out = std::process::Command::new(&self.exe)
+            .arg("--version")
+            .output()
+            .map_err(|e| self.spawn_error(e))?;
+        Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    /// Get the version of the Futhark compiler by running `futhark --version`
+    pub fn version(&self) -> Result<Version, Error> {
+        let s = self.version_string()?;
+        Version::parse(&s).ok_or(Error::InvalidVersion(s))
+    }
+
+    /// Hash everything that affects the compiler output: the sources, the options
+    /// and the compiler itself
+    fn stamp(&self, version: &str) -> String {
+        // Every part is prefixed with its length so that the concatenation is unambiguous
+        let mut data = Vec::new();
+        let mut add = |bytes: &[u8]| {
+            data.extend((bytes.len() as u64).to_le_bytes());
+            data.extend(bytes);
+        };
+        add(version.as_bytes());
+        add(self.exe.as_os_str().as_encoded_bytes());
+        if let Some(meta) = find_executable(&self.exe).and_then(|p| p.metadata().ok()) {
+            add(format!("{} {:?}", meta.len(), meta.modified().ok()).as_bytes());
+        }
+        add(self.backend.to_str().as_bytes());
+        add(format!(
+            "{:?} {} {:?}",
+            self.options(),
+            self.run_tests,
+            self.autotune
+        )
+        .as_bytes());
+
+        let mut files = package::source_files(&self.src);
+        files.sort();
+        for file in files {
+            add(file.as_os_str().as_encoded_bytes());
+            add(&std::fs::read(&file).unwrap_or_default());
+        }
+        crate::sha256::sha256_hex(&data)
+    }
+
+    /// Check whether the outputs of a previous compilation with the same stamp exist
+    fn is_up_to_date(&self, output: &std::path::Path, stamp: &str) -> bool {
+        let mut outputs = vec![
+            output.with_extension("c"),
+            output.with_extension("h"),
+            output.with_extension("json"),
+        ];
+        if self.backend == Backend::ISPC {
+            outputs.push(output.with_extension("kernels.ispc"));
+        }
+        if self.autotune.is_some() {
+            outputs.push(output.with_extension("tuning"));
+        }
+        !self.force_rebuild
+            && outputs.iter().all(|p| p.exists())
+            && std::fs::read_to_string(output.with_extension("stamp"))
+                .ok()
+                .as_deref()
+                == Some(stamp)
+    }
+
+    fn output_dir(&self) -> std::path::PathBuf {
+        match &self.output_dir {
+            Some(dir) => dir.clone(),
+            None => package::source_dir(&self.src).to_path_buf(),
+        }
+    }
+
+    /// Output path, without extension
+    pub(crate) fn output(&self) -> std::path::PathBuf {
+        match &self.output_name {
+            Some(name) => self.output_dir().join(name),
+            None => self
+                .output_dir()
+                .join(self.src.with_extension("").file_name().unwrap()),
+        }
+    }
+
+    /// Get the command that will be used to run the Futhark compiler
+    pub fn command(&self) -> std::process::Command {
+        let mut cmd = std::process::Command::new(&self.exe);
+        cmd.arg(self.backend.to_str())
+            .args(self.options())
+            .arg("-o")
+            .arg(self.output())
+            .arg("--lib")
+            .arg(&self.src);
+        cmd
+    }
+
+    /// Get the command that will be used to run the Futhark compiler as a string
+    pub fn command_line(&self) -> String {
+        let cmd = self.command();
+        std::iter::once(cmd.get_program())
+            .chain(cmd.get_args())
+            .map(|arg| {
+                let arg = arg.to_string_lossy();
+                if arg.contains(char::is_whitespace) {
+                    format!("{arg:?}")
+                } else {
+                    arg.into_owned()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     /// Compile the package
     ///
     /// This will generate a C file, C header file and manifest
+    ///
+    /// If the sources, options and compiler are unchanged since the last compilation into the
+    /// same output directory then the existing outputs are reused, see `with_force_rebuild`
     pub fn compile(&self) -> Result<Package, Error> {
-        // Create -o argument
-        let output = &self
-            .output_dir
-            .join(self.src.with_extension("").file_name().unwrap());
-
-        let ok = std::process::Command::new(&self.exe)
-            .arg(self.backend.to_str())
-            .args(&self.extra_args)
-            .args(["-o", &output.to_string_lossy()])
-            .arg("--lib")
+        Compiler::compile_all(std::slice::from_ref(self), None)
+            .pop()
+            .unwrap()
+    }
+
+    /// Compile several packages concurrently, running up to `jobs` instances of the Futhark
+    /// compiler at once. By default the number of jobs is taken from the `NUM_JOBS`
+    /// environment variable set by cargo, or the available parallelism otherwise
+    ///
+    /// The results are in the same order as `compilers`. Every compiler must have a
+    /// distinct output path, see `with_output_dir` and `with_output_name`, otherwise
+    /// `Error::DuplicateOutput` is returned for all but the first
+    pub fn compile_all(compilers: &[Compiler], jobs: Option<usize>) -> Vec<Result<Package, Error>> {
+        let jobs = jobs
+            .or_else(|| std::env::var("NUM_JOBS").ok()?.parse().ok())
+            .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(1)
+            .clamp(1, compilers.len().max(1));
+
+        let mut outputs = std::collections::BTreeSet::new();
+        let duplicate: Vec<bool> = compilers
+            .iter()
+            .map(|c| !outputs.insert(c.output()))
+            .collect();
+
+        let next = std::sync::atomic::AtomicUsize::new(0);
+        let results = std::sync::Mutex::new(Vec::new());
+        let work = || loop {
+            let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let compiler = match compilers.get(i) {
+                Some(c) => c,
+                None => break,
+            };
+            let res = if duplicate[i] {
+                Err(Error::DuplicateOutput(compiler.output()))
+            } else {
+                compiler.compile_one()
+            };
+            results.lock().unwrap().push((i, res));
+        };
+
+        if jobs == 1 {
+            work();
+        } else {
+            std::thread::scope(|scope| {
+                for _ in 0..jobs {
+                    scope.spawn(work);
+                }
+            });
+        }
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, res)| res).collect()
+    }
+
+    fn compile_one(&self) -> Result<Package, Error> {
+        self.check_paths()?;
+
+        let target = std::env::var("TARGET").unwrap_or_default();
+        let mut compiler = std::borrow::Cow::Borrowed(self);
+        for backend in &self.fallback_backends {
+            if package::sdk_available(compiler.backend, &target) {
+                break;
+            }
+            warn(&format!(
+                "the SDK of the {} backend was not found, falling back to {}",
+                compiler.backend.to_str(),
+                backend.to_str()
+            ));
+            compiler.to_mut().backend = *backend;
+        }
+        compiler.compile_backend()
+    }
+
+    /// Make sure the source file exists and the output directory can be written to
+    /// before running the compiler, which doesn't report these problems clearly
+    fn check_paths(&self) -> Result<(), Error> {
+        if !self.src.is_file() {
+            return Err(Error::SourceNotFound(self.src.clone()));
+        }
+        if self.src.extension() != Some(std::ffi::OsStr::new("fut")) {
+            return Err(Error::InvalidSourceFile(self.src.clone()));
+        }
+
+        let dir = self.output_dir();
+        std::fs::create_dir_all(&dir).map_err(|error| Error::InvalidOutputDir {
+            path: dir.clone(),
+            error,
+        })?;
+        Ok(())
+    }
+
+    fn compile_backend(&self) -> Result<Package, Error> {
+        let version_string = self.version_string()?;
+        let version = Version::parse(&version_string)
+            .ok_or_else(|| Error::InvalidVersion(version_string.clone()))?;
+        if version < Version::MINIMUM {
+            return Err(Error::UnsupportedCompilerVersion {
+                found: version,
+                required: Version::MINIMUM,
+            });
+        }
+
+        if self.pkg_sync {
+            self.pkg_sync()?;
+        }
+
+        let output = &self.output();
+        let stamp = self.stamp(&version_string);
+        if self.is_up_to_date(output, &stamp) {
+            return self.package(output, &version_string);
+        }
+
+        if self.verbose {
+            let exe = find_executable(&self.exe).unwrap_or_else(|| self.exe.clone());
+            warn(&format!("using futhark {version} from {}", exe.display()));
+            let cwd = std::env::current_dir()?;
+            warn(&format!(
+                "running {} in {}",
+                self.command_line(),
+                cwd.display()
+            ));
+        }
+        let out = self.command().output().map_err(|e| self.spawn_error(e))?;
+
+        let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+        if !out.status.success() {
+            return Err(Error::CompilationFailed {
+                src: self.src.clone(),
+                stderr,
+                exit_code: out.status.code(),
+            });
+        }
+
+        // Forward any warnings printed during a successful compilation, stdout is
+        // redirected to stderr since build scripts use stdout to talk to cargo
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        if self.warnings {
+            for msg in compiler_messages(&stdout).chain(compiler_messages(&stderr)) {
+                warn(&format!("futhark: {msg}"));
+            }
+        } else {
+            eprint!("{stdout}");
+            eprint!("{stderr}");
+        }
+
+        if self.run_tests {
+            self.test()?;
+        }
+        if let Some(options) = &self.autotune {
+            self.autotune(options, output)?;
+        }
+
+        std::fs::write(output.with_extension("stamp"), stamp)?;
+        self.package(output, &version_string)
+    }
+
+    /// Run `futhark test` on the source file
+    fn test(&self) -> Result<(), Error> {
+        let mut cmd = std::process::Command::new(&self.exe);
+        cmd.arg("test")
+            .arg(format!("--backend={}", self.backend.to_str()))
+            .arg(format!("--futhark={}", self.exe.display()));
+        for arg in self.options() {
+            cmd.arg(format!("--pass-compiler-option={arg}"));
+        }
+        let out = cmd
             .arg(&self.src)
-            .status()?
-            .success();
-
-        if !ok {
-            return Err(Error::CompilationFailed);
-        }
-
-        // Load manifest after successful compilation
-        let manifest = Manifest::parse_file(output.with_extension("json"))?;
-        let c_file = output.with_extension("c");
-        let h_file = output.with_extension("h");
-        Ok(Package {
-            manifest,
-            c_file,
-            h_file,
-            src: self.src.clone(),
-        })
+            .output()
+            .map_err(|e| self.spawn_error(e))?;
+
+        let report = String::from_utf8_lossy(&out.stdout).into_owned()
+            + &String::from_utf8_lossy(&out.stderr);
+        eprint!("{report}");
+        if out.status.success() {
+            return Ok(());
+        }
+
+        if is_device_error(&report) {
+            warn(&format!(
+                "skipping futhark test, the {} backend is unable to run on this machine",
+                self.backend.to_str()
+            ));
+            return Ok(());
+        }
+        Err(Error::FutharkTestsFailed { report })
+    }
+
+    /// Run `futhark autotune` and copy the tuning file into the output directory
+    fn autotune(&self, options: &AutotuneOptions, output: &std::path::Path) -> Result<(), Error> {
+        let mut cmd = std::process::Command::new(&self.exe);
+        cmd.arg("autotune")
+            .arg(format!("--backend={}", self.backend.to_str()))
+            .arg(format!("--futhark={}", self.exe.display()))
+            .args(options.args());
+        for arg in self.options() {
+            cmd.arg(format!("--pass-compiler-option={arg}"));
+        }
+        let out = cmd
+            .arg(&self.src)
+            .output()
+            .map_err(|e| self.spawn_error(e))?;
+        if !out.status.success() {
+            return Err(Error::AutotuneFailed {
+                stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+                exit_code: out.status.code(),
+            });
+        }
+
+        // The tuning file is written next to the source file
+        let mut tuning = self.src.clone().into_os_string();
+        tuning.push(".tuning");
+        std::fs::copy(tuning, output.with_extension("tuning"))?;
+        Ok(())
+    }
+
+    /// Load the outputs of a successful compilation
+    fn package(&self, output: &std::path::Path, version: &str) -> Result<Package, Error> {
+        let mut pkg = Package::from_manifest(output.with_extension("json"))?;
+        pkg.src = self.src.clone();
+        // The version and git commit, without the copyright notice that follows them
+        let version: Vec<_> = version
+            .lines()
+            .map(str::trim)
+            .take_while(|line| !line.starts_with("Copyright"))
+            .filter(|line| !line.is_empty())
+            .collect();
+        pkg.compiler_version = Some(version.join(", "));
+        pkg.ispc_exe = self.ispc_exe.clone();
+        pkg.verbose = self.verbose;
+        if self.autotune.is_none() {
+            pkg.tuning_file = None;
+        }
+        Ok(pkg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiler(src: impl AsRef<std::path::Path>) -> Compiler {
+        Compiler::new(Backend::C, src).with_executable_name("futhark")
+    }
+
+    /// Create a directory with `prog.fut` and a `futhark` script running `body` for every
+    /// command except `--version`, the backend of each call is appended to `calls`
+    #[cfg(unix)]
+    fn fake_futhark(name: &str, body: &str) -> std::path::PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("futhark-bindgen-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("prog.fut"), "entry main (x: i32) = x + 1\n").unwrap();
+        let exe = dir.join("futhark");
+        let script = format!(
+            "#!/bin/sh\n\
+            if [ \"$1\" = --version ]; then echo 'Futhark 0.25.2'; exit 0; fi\n\
+            echo \"$1\" >> \"$(dirname \"$0\")/calls\"\n\
+            {body}\n"
+        );
+        std::fs::write(&exe, script).unwrap();
+        std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    }
+
+    /// Script body writing an empty manifest, the compilation succeeds
+    #[cfg(unix)]
+    const COMPILE_OK: &str = r#"backend=$1
+while [ "$1" != -o ]; do shift; done
+echo "{\"backend\": \"$backend\", \"version\": \"0.25.2\", \"entry_points\": {}, \"types\": {}}" > "$2.json""#;
+
+    #[cfg(unix)]
+    fn fake_compiler(dir: &std::path::Path, backend: Backend) -> Compiler {
+        Compiler::new(backend, dir.join("prog.fut"))
+            .with_executable_name(dir.join("futhark"))
+            .with_output_dir(dir.join("out"))
+            .with_force_rebuild(true)
+    }
+
+    #[cfg(unix)]
+    fn calls(dir: &std::path::Path) -> String {
+        std::fs::read_to_string(dir.join("calls")).unwrap_or_default()
+    }
+
+    /// Compiler errors are returned as they are, whether or not they point into the program
+    #[cfg(unix)]
+    #[test]
+    fn no_fallback_on_compiler_errors() {
+        for (name, stderr) in [
+            ("fallback-program-error", "Error at prog.fut:1:22-22:"),
+            ("fallback-other-error", "futhark: internal compiler error"),
+        ] {
+            let dir = fake_futhark(name, &format!("echo '{stderr}' >&2\nexit 1"));
+            let res = fake_compiler(&dir, Backend::C)
+                .with_fallback_backends(&[Backend::Multicore])
+                .compile();
+            match res {
+                Err(Error::CompilationFailed {
+                    stderr: out,
+                    exit_code,
+                    ..
+                }) => {
+                    assert_eq!(out.trim(), stderr);
+                    assert_eq!(exit_code, Some(1));
+                }
+                res => panic!("expected a compilation failure, got {res:?}"),
+            }
+            assert_eq!(calls(&dir), "c\n");
+        }
+    }
+
+    /// A program that doesn't type check is reported with the output and exit code of the
+    /// compiler
+    #[cfg(unix)]
+    #[test]
+    fn compilation_failure() {
+        let stderr = "Error at prog.fut:1:22-22:\nUnknown name \"y\"";
+        let dir = fake_futhark(
+            "compilation-failure",
+            &format!("printf '{stderr}' >&2\nexit 2"),
+        );
+        std::fs::write(dir.join("prog.fut"), "entry main (x: i32) = y\n").unwrap();
+
+        let err = fake_compiler(&dir, Backend::C).compile().unwrap_err();
+        match &err {
+            Error::CompilationFailed {
+                src,
+                stderr: out,
+                exit_code,
+            } => {
+                assert_eq!(src, &dir.join("prog.fut"));
+                assert_eq!(out, stderr);
+                assert_eq!(*exit_code, Some(2));
+            }
+            err => panic!("expected a compilation failure, got {err:?}"),
+        }
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Compilation of {} failed with exit code 2\n{stderr}",
+                dir.join("prog.fut").display()
+            )
+        );
+        assert!(!dir.join("out").join("prog.stamp").exists());
+    }
+
+    /// The error of each failed compilation names its source file
+    #[cfg(unix)]
+    #[test]
+    fn compile_all_names_failed_program() {
+        let body = format!(
+            "for src; do :; done\n\
+            case \"$src\" in *bad.fut) echo 'Error at bad.fut:1:1-1:' >&2; exit 1;; esac\n\
+            {COMPILE_OK}"
+        );
+        let dir = fake_futhark("compile-all", &body);
+        std::fs::write(dir.join("bad.fut"), "entry main =\n").unwrap();
+        let compilers = [
+            fake_compiler(&dir, Backend::C),
+            Compiler {
+                src: dir.join("bad.fut"),
+                ..fake_compiler(&dir, Backend::C)
+            },
+        ];
+
+        let results = Compiler::compile_all(&compilers, Some(2));
+        assert!(results[0].is_ok());
+        let err = results[1].as_ref().unwrap_err();
+        assert!(matches!(err, Error::CompilationFailed { src, .. } if src == &dir.join("bad.fut")));
+        let msg = err.to_string();
+        assert!(msg.starts_with(&format!(
+            "Compilation of {} failed",
+            dir.join("bad.fut").display()
+        )));
+    }
+
+    /// Sources with the same name in different directories would overwrite each other's output
+    #[cfg(unix)]
+    #[test]
+    fn same_name_in_different_directories() {
+        let dir = fake_futhark("same-name", COMPILE_OK);
+        for sub in ["a", "b"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+            std::fs::write(dir.join(sub).join("prog.fut"), "entry main (x: i32) = x\n").unwrap();
+        }
+        let compilers: Vec<_> = ["a", "b"]
+            .iter()
+            .map(|sub| Compiler {
+                src: dir.join(sub).join("prog.fut"),
+                ..fake_compiler(&dir, Backend::C)
+            })
+            .collect();
+
+        let results = Compiler::compile_all(&compilers, None);
+        assert!(results[0].is_ok());
+        assert!(
+            matches!(&results[1], Err(Error::DuplicateOutput(path)) if path == &dir.join("out").join("prog"))
+        );
+        assert_eq!(calls(&dir), "c\n");
+    }
+
+    /// A backend whose SDK isn't found is skipped before compiling
+    #[cfg(unix)]
+    #[test]
+    fn fallback_on_missing_sdk() {
+        let dir = fake_futhark("fallback-sdk", COMPILE_OK);
+        let compiler = fake_compiler(&dir, Backend::CUDA).with_fallback_backends(&[Backend::C]);
+
+        std::env::set_var("FUTHARK_BINDGEN_CUDA_LIB_DIR", dir.join("missing"));
+        let pkg = compiler.compile().unwrap();
+        assert_eq!(pkg.manifest.backend, Backend::C);
+        assert_eq!(calls(&dir), "c\n");
+
+        std::env::set_var("FUTHARK_BINDGEN_CUDA_LIB_DIR", &dir);
+        let pkg = compiler.compile().unwrap();
+        std::env::remove_var("FUTHARK_BINDGEN_CUDA_LIB_DIR");
+        assert_eq!(pkg.manifest.backend, Backend::CUDA);
+        assert_eq!(calls(&dir), "c\ncuda\n");
+    }
+
+    #[test]
+    fn command_line() {
+        let c = compiler("src/prog.fut");
+        assert_eq!(c.command_line(), "futhark c -o src/prog --lib src/prog.fut");
+
+        let c = compiler("src/prog.fut")
+            .with_extra_args(vec!["--Werror".to_string()])
+            .with_safe(true)
+            .with_verbosity(2)
+            .with_profile(true)
+            .with_entry_points(&["a", "b"]);
+        assert_eq!(
+            c.command_line(),
+            "futhark c --Werror --safe -v -v --profile --entry-point=a --entry-point=b \
+            -o src/prog --lib src/prog.fut"
+        );
+
+        let c = compiler("my src/prog.fut")
+            .with_output_dir("out dir")
+            .with_output_name("lib");
+        assert_eq!(
+            c.command_line(),
+            r#"futhark c -o "out dir/lib" --lib "my src/prog.fut""#
+        );
+    }
+
+    /// Paths that aren't valid UTF-8 are passed to the compiler unchanged
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let src = std::path::Path::new(OsStr::from_bytes(b"dir\xff/prog.fut"));
+        let c = compiler(src);
+        let cmd = c.command();
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                OsStr::new("c"),
+                OsStr::new("-o"),
+                OsStr::from_bytes(b"dir\xff/prog"),
+                OsStr::new("--lib"),
+                src.as_os_str(),
+            ]
+        );
+        assert_eq!(
+            c.command_line(),
+            "futhark c -o dir\u{fffd}/prog --lib dir\u{fffd}/prog.fut"
+        );
     }
 }
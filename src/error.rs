@@ -2,15 +2,314 @@
 #[derive(Debug)]
 pub enum Error {
     /// Compilation failed
-    CompilationFailed,
+    CompilationFailed {
+        /// Source file that failed to compile
+        src: std::path::PathBuf,
 
-    /// Json decoding error
+        /// Output written to stderr by the Futhark compiler
+        stderr: String,
+
+        /// Exit code of the Futhark compiler, `None` if it was terminated by a signal
+        exit_code: Option<i32>,
+    },
+
+    /// `futhark pkg sync` failed
+    PkgSyncFailed {
+        /// Output written to stderr by `futhark pkg sync`
+        stderr: String,
+
+        /// Exit code of `futhark pkg sync`, `None` if it was terminated by a signal
+        exit_code: Option<i32>,
+    },
+
+    /// The Futhark compiler could not be found
+    ExecutableNotFound {
+        /// Name or path of the executable
+        exe: String,
+    },
+
+    /// `futhark test` reported failing tests
+    FutharkTestsFailed {
+        /// Output of `futhark test`
+        report: String,
+    },
+
+    /// `futhark autotune` failed
+    AutotuneFailed {
+        /// Output written to stderr by `futhark autotune`
+        stderr: String,
+
+        /// Exit code of `futhark autotune`, `None` if it was terminated by a signal
+        exit_code: Option<i32>,
+    },
+
+    /// The version of the Futhark compiler could not be determined
+    InvalidVersion(String),
+
+    /// The Futhark compiler is too old
+    UnsupportedCompilerVersion {
+        /// Version of the installed compiler
+        found: crate::Version,
+
+        /// Minimum supported version
+        required: crate::Version,
+    },
+
+    /// The manifest was written by an unsupported version of the Futhark compiler
+    UnsupportedManifestVersion {
+        /// Version listed in the manifest
+        found: crate::Version,
+
+        /// Supported versions, see `Version::SUPPORTED_MANIFESTS`
+        supported: std::ops::Range<crate::Version>,
+    },
+
+    /// Unknown backend name
+    InvalidBackend(String),
+
+    /// A required environment variable is not set, e.g. `OUT_DIR` when not running
+    /// from a build script
+    MissingEnvVar(String),
+
+    /// The source file doesn't exist
+    SourceNotFound(std::path::PathBuf),
+
+    /// The source path doesn't name a `.fut` file
+    InvalidSourceFile(std::path::PathBuf),
+
+    /// The output directory doesn't exist and could not be created
+    InvalidOutputDir {
+        /// Output directory
+        path: std::path::PathBuf,
+
+        /// Error returned when creating the directory
+        error: std::io::Error,
+    },
+
+    /// Two compilers passed to `Compiler::compile_all` would write to the same output path
+    DuplicateOutput(std::path::PathBuf),
+
+    /// Two programs passed to `build_many` would be placed in the same module
+    DuplicateModule {
+        /// Name of the module
+        name: String,
+
+        /// First source file
+        first: std::path::PathBuf,
+
+        /// Second source file
+        second: std::path::PathBuf,
+    },
+
+    /// An entry point passed to `Config::with_entry_points`, `Config::without_entry_points` or
+    /// `Config::rename_entry` doesn't exist in the manifest
+    UnknownEntryPoint(String),
+
+    /// A type passed to `Config::rename_type` doesn't exist in the manifest
+    UnknownType(String),
+
+    /// Two entry points get the same name in the generated code, after renaming them and
+    /// replacing characters that aren't allowed in identifiers
+    NameCollision {
+        /// Name of the generated function
+        name: String,
+
+        /// First entry point
+        first: String,
+
+        /// Second entry point
+        second: String,
+    },
+
+    /// A line of `Package::tuning_file` isn't a `name=value` pair
+    InvalidTuningFile {
+        /// Tuning file
+        path: std::path::PathBuf,
+
+        /// The malformed line
+        line: String,
+    },
+
+    /// No code generator is available for the output file
+    UnsupportedOutputLanguage(std::path::PathBuf),
+
+    /// Compiling or linking the generated C code failed
+    LinkFailed(String),
+
+    /// The manifest doesn't match the expected format
+    InvalidManifest {
+        /// Manifest file, if it was read from a file
+        path: Option<std::path::PathBuf>,
+
+        /// Part of the manifest that failed to decode, e.g. "entry point `main`, input 0"
+        context: Option<String>,
+
+        /// Description of the problem
+        message: String,
+    },
+
+    /// The generated code couldn't be parsed when formatting it, this is a bug in the code
+    /// generator
+    InvalidGeneratedCode {
+        /// Output file
+        path: std::path::PathBuf,
+
+        /// Error reported by the formatter
+        message: String,
+    },
+
+    /// Json encoding or decoding error
     Json(serde_json::Error),
 
     /// std::io::Error
     Io(std::io::Error),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::CompilationFailed {
+                src,
+                stderr,
+                exit_code,
+            } => {
+                write!(fmt, "Compilation of {} failed", src.display())?;
+                if let Some(code) = exit_code {
+                    write!(fmt, " with exit code {code}")?;
+                }
+                if !stderr.is_empty() {
+                    write!(fmt, "\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            Error::PkgSyncFailed { stderr, exit_code } => {
+                match exit_code {
+                    Some(code) => write!(fmt, "futhark pkg sync failed with exit code {code}")?,
+                    None => write!(fmt, "futhark pkg sync failed")?,
+                }
+                if !stderr.is_empty() {
+                    write!(fmt, "\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            Error::ExecutableNotFound { exe } => write!(
+                fmt,
+                "Unable to find the Futhark compiler {exe:?}, make sure futhark is installed and \
+                in your PATH or set its path using FUTHARK_EXE or Compiler::with_executable_name"
+            ),
+            Error::FutharkTestsFailed { report } => {
+                write!(fmt, "futhark test failed\n{}", report.trim_end())
+            }
+            Error::AutotuneFailed { stderr, exit_code } => {
+                match exit_code {
+                    Some(code) => write!(fmt, "futhark autotune failed with exit code {code}")?,
+                    None => write!(fmt, "futhark autotune failed")?,
+                }
+                if !stderr.is_empty() {
+                    write!(fmt, "\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            Error::InvalidVersion(s) => {
+                write!(fmt, "Unable to parse Futhark compiler version: {s:?}")
+            }
+            Error::UnsupportedCompilerVersion { found, required } => {
+                write!(fmt, "futhark {found} found, {required} required")
+            }
+            Error::UnsupportedManifestVersion { found, supported } => write!(
+                fmt,
+                "Manifest written by futhark {found} is not supported, expected at least {} and \
+                less than {}",
+                supported.start, supported.end
+            ),
+            Error::InvalidBackend(name) => {
+                let names: Vec<_> = crate::Backend::all().iter().map(|b| b.to_str()).collect();
+                write!(
+                    fmt,
+                    "Invalid backend {name:?}, expected one of: {}",
+                    names.join(", ")
+                )
+            }
+            Error::MissingEnvVar(name) => write!(fmt, "Environment variable {name} is not set"),
+            Error::SourceNotFound(path) => {
+                write!(fmt, "Futhark source file not found: {}", path.display())
+            }
+            Error::InvalidSourceFile(path) => write!(
+                fmt,
+                "Invalid Futhark source file, expected a path to a .fut file: {}",
+                path.display()
+            ),
+            Error::InvalidOutputDir { path, error } => write!(
+                fmt,
+                "Unable to create output directory {}: {error}",
+                path.display()
+            ),
+            Error::DuplicateOutput(path) => write!(
+                fmt,
+                "Multiple programs would be compiled to {}, use Compiler::with_output_name to \
+                give them distinct names",
+                path.display()
+            ),
+            Error::DuplicateModule {
+                name,
+                first,
+                second,
+            } => write!(
+                fmt,
+                "Programs {} and {} would both be placed in module {name}, rename one of them",
+                first.display(),
+                second.display()
+            ),
+            Error::UnknownEntryPoint(name) => write!(fmt, "Unknown entry point: {name}"),
+            Error::UnknownType(name) => write!(fmt, "Unknown type: {name}"),
+            Error::NameCollision {
+                name,
+                first,
+                second,
+            } => write!(
+                fmt,
+                "Entry points {first} and {second} would both be generated as {name}, use \
+                Config::rename_entry to give them distinct names"
+            ),
+            Error::InvalidTuningFile { path, line } => write!(
+                fmt,
+                "Invalid tuning file {}: expected name=value, found `{line}`",
+                path.display()
+            ),
+            Error::UnsupportedOutputLanguage(path) => write!(
+                fmt,
+                "Unable to detect output language for {}",
+                path.display()
+            ),
+            Error::LinkFailed(msg) => write!(fmt, "Linking failed: {msg}"),
+            Error::InvalidManifest {
+                path,
+                context,
+                message,
+            } => {
+                write!(fmt, "Unable to decode manifest")?;
+                if let Some(path) = path {
+                    write!(fmt, " {}", path.display())?;
+                }
+                if let Some(context) = context {
+                    write!(fmt, ", {context}")?;
+                }
+                write!(fmt, ": {message}")
+            }
+            Error::InvalidGeneratedCode { path, message } => write!(
+                fmt,
+                "Unable to parse generated code in {}, this is a bug in futhark-bindgen\n{}",
+                path.display(),
+                message.trim_end()
+            ),
+            Error::Json(e) => write!(fmt, "Json error: {e}"),
+            Error::Io(e) => write!(fmt, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
         Error::Json(e)
@@ -1,10 +1,13 @@
 use crate::*;
+use std::collections::BTreeSet;
 
 mod ocaml;
 mod rust;
 
 pub use ocaml::OCaml;
 pub use rust::Rust;
+#[cfg(feature = "build")]
+pub(crate) use rust::{raw_ident, rust_ident};
 
 pub(crate) fn first_uppercase(s: &str) -> String {
     let mut s = s.to_string();
@@ -25,13 +28,234 @@ pub(crate) fn convert_struct_name(s: &str) -> &str {
         .unwrap()
 }
 
+/// Replace characters that can't be part of an identifier, such as the `'` allowed in Futhark
+/// names, with `_`
+pub(crate) fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Write `contents` to `path` unless the file already contains them, so its modification time
+/// only changes when the generated code does
+pub(crate) fn write_if_changed(path: &std::path::Path, contents: &[u8]) -> Result<(), Error> {
+    if std::fs::read(path).ok().as_deref() != Some(contents) {
+        std::fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+/// Futhark signature of an entry point, used in the generated documentation
+pub(crate) fn entry_signature(name: &str, entry: &manifest::Entry) -> String {
+    let unique = |u: bool| if u { "*" } else { "" };
+    let mut sig = format!("entry {name}");
+    for input in &entry.inputs {
+        sig += &format!(
+            " ({}: {}{})",
+            input.name,
+            unique(input.unique),
+            input.r#type
+        );
+    }
+    let outputs: Vec<_> = entry
+        .outputs
+        .iter()
+        .map(|o| format!("{}{}", unique(o.unique), o.r#type))
+        .collect();
+    match outputs.len() {
+        1 => sig + " : " + &outputs[0],
+        _ => sig + &format!(" : ({})", outputs.join(", ")),
+    }
+}
+
+/// Values making up the stored form of a type, as the 4 byte type name of the binary data
+/// format and the rank. `None` if the manifest doesn't say how the type is represented, which is
+/// only known for scalars, arrays and records of those
+pub(crate) fn stored_layout(manifest: &Manifest, name: &str) -> Option<Vec<(String, usize)>> {
+    match manifest::TypeRef::parse(name) {
+        manifest::TypeRef::Scalar(elemtype) => Some(vec![(format!("{:>4}", elemtype.to_str()), 0)]),
+        manifest::TypeRef::Array { elemtype, rank } => {
+            Some(vec![(format!("{:>4}", elemtype.to_str()), rank)])
+        }
+        manifest::TypeRef::Opaque(name) => match manifest.types.get(name)? {
+            manifest::Type::Opaque(manifest::OpaqueType {
+                record: Some(record),
+                ..
+            }) => {
+                let mut layout = Vec::new();
+                for field in &record.fields {
+                    layout.extend(stored_layout(manifest, &field.r#type)?);
+                }
+                Some(layout)
+            }
+            _ => None,
+        },
+    }
+}
+
+/// Futhark definition of an opaque type, used in the generated documentation
+pub(crate) fn opaque_description(name: &str, ty: &manifest::OpaqueType) -> String {
+    let def = if let Some(record) = &ty.record {
+        let is_tuple = record
+            .fields
+            .iter()
+            .enumerate()
+            .all(|(i, f)| f.name == i.to_string());
+        if is_tuple {
+            let fields: Vec<_> = record.fields.iter().map(|f| f.r#type.as_str()).collect();
+            format!("({})", fields.join(", "))
+        } else {
+            let fields: Vec<_> = record
+                .fields
+                .iter()
+                .map(|f| format!("{}: {}", f.name, f.r#type))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    } else if let Some(sum) = &ty.sum {
+        let variants: Vec<_> = sum
+            .variants
+            .iter()
+            .map(|v| {
+                let mut s = format!("#{}", v.name);
+                for p in &v.payload {
+                    s += " ";
+                    s += p;
+                }
+                s
+            })
+            .collect();
+        variants.join(" | ")
+    } else {
+        return name.to_string();
+    };
+    if def == name {
+        def
+    } else {
+        format!("{name} = {def}")
+    }
+}
+
+/// Visibility of the generated Rust items, see `Config::with_visibility`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Visibility {
+    /// `pub`
+    #[default]
+    Pub,
+
+    /// `pub(crate)`
+    PubCrate,
+
+    /// Only visible in the module the bindings are included in
+    Private,
+}
+
+impl Visibility {
+    /// Keyword used for the items, including a trailing space unless it's empty
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Visibility::Pub => "pub ",
+            Visibility::PubCrate => "pub(crate) ",
+            Visibility::Private => "",
+        }
+    }
+}
+
+/// Naming convention applied to entry point names, see `Config::with_rename_style`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameStyle {
+    /// `matmulT_f32` becomes `matmul_t_f32`
+    SnakeCase,
+}
+
+impl RenameStyle {
+    /// Convert `name` to this style
+    ///
+    /// ```
+    /// use futhark_bindgen::RenameStyle;
+    /// assert_eq!(RenameStyle::SnakeCase.apply("matmulT_f32"), "matmul_t_f32");
+    /// assert_eq!(RenameStyle::SnakeCase.apply("HTTPServer"), "http_server");
+    /// ```
+    pub fn apply(&self, name: &str) -> String {
+        match self {
+            RenameStyle::SnakeCase => {
+                let chars: Vec<char> = name.chars().collect();
+                let mut out = String::with_capacity(name.len());
+                for (i, &c) in chars.iter().enumerate() {
+                    if c.is_uppercase() && i > 0 && chars[i - 1] != '_' {
+                        let prev_lower = chars[i - 1].is_lowercase() || chars[i - 1].is_numeric();
+                        let next_lower = chars.get(i + 1).is_some_and(|c| c.is_lowercase());
+                        if prev_lower || (chars[i - 1].is_uppercase() && next_lower) {
+                            out.push('_');
+                        }
+                    }
+                    out.extend(c.to_lowercase());
+                }
+                out
+            }
+        }
+    }
+}
+
 /// Code generation config
 pub struct Config {
     /// Output file
     pub output_path: std::path::PathBuf,
 
-    /// Path to output file
-    pub output_file: std::fs::File,
+    /// Generated code, written to `output_path` at the end of `Generate::generate` if it differs
+    /// from the contents of the file
+    pub output_file: Vec<u8>,
+
+    /// Only generate code for these entry points, see `Config::with_entry_points`
+    pub entry_points: Option<Vec<String>>,
+
+    /// Skip these entry points, see `Config::without_entry_points`
+    pub excluded_entry_points: Vec<String>,
+
+    /// Write a description of the generated code, see `Config::with_metadata`
+    pub metadata: bool,
+
+    /// Make the C declarations public, see `Config::with_raw_bindings`
+    pub raw_bindings: bool,
+
+    /// Generate conversions to and from `ndarray`, see `Config::with_ndarray`
+    pub ndarray: bool,
+
+    /// Generate `serde` support for arrays, see `Config::with_serde`
+    pub serde: bool,
+
+    /// Format the generated code, see `Config::with_format`
+    pub format: bool,
+
+    /// Visibility of the generated Rust items, see `Config::with_visibility`
+    pub visibility: Visibility,
+
+    /// Module the generated Rust code is wrapped in, see `Config::with_module`
+    pub module: Option<String>,
+
+    /// Allow common lints in the generated Rust code, see `Config::with_allow_lints`
+    pub allow_lints: bool,
+
+    /// Entry point names, see `Config::rename_entry`
+    pub entry_renames: BTreeMap<String, String>,
+
+    /// Rust type names, see `Config::rename_type`
+    pub type_renames: BTreeMap<String, String>,
+
+    /// Naming convention for entry points, see `Config::with_rename_style`
+    pub rename_style: Option<RenameStyle>,
+
+    /// Name of the trait describing the entry points, see `Config::with_entry_trait`
+    pub entry_trait: Option<String>,
+
+    /// Cargo features of entry points, see `Config::feature_gate`
+    pub feature_gates: BTreeMap<String, String>,
+
+    /// Generate the `Array<T, R>` alias and traits, see `Config::with_generic_arrays`
+    pub generic_arrays: bool,
+
+    /// Include the Futhark source in the generated code, see `Config::with_embedded_source`
+    pub embedded_source: bool,
 }
 
 impl Config {
@@ -39,17 +263,349 @@ impl Config {
     pub fn new(output: impl AsRef<std::path::Path>) -> Result<Config, Error> {
         Ok(Config {
             output_path: output.as_ref().to_path_buf(),
-            output_file: std::fs::File::create(output)?,
+            output_file: Vec::new(),
+            entry_points: None,
+            excluded_entry_points: Vec::new(),
+            metadata: false,
+            raw_bindings: false,
+            ndarray: false,
+            serde: false,
+            format: true,
+            visibility: Visibility::Pub,
+            module: None,
+            allow_lints: false,
+            entry_renames: BTreeMap::new(),
+            type_renames: BTreeMap::new(),
+            rename_style: None,
+            entry_trait: None,
+            feature_gates: BTreeMap::new(),
+            generic_arrays: false,
+            embedded_source: false,
         })
     }
+
+    /// Make the declarations of the C API available as `pub mod sys` in the generated Rust code,
+    /// the pointers used by the safe wrappers can be passed to these functions directly
+    pub fn with_raw_bindings(mut self, raw_bindings: bool) -> Self {
+        self.raw_bindings = raw_bindings;
+        self
+    }
+
+    /// Add `from_ndarray` and `to_ndarray` to the generated Rust array types, the crate using
+    /// the bindings needs to depend on `ndarray`
+    pub fn with_ndarray(mut self, ndarray: bool) -> Self {
+        self.ndarray = ndarray;
+        self
+    }
+
+    /// Implement `serde::Serialize` for the generated Rust array types and add `ArrayData`, which
+    /// arrays can be deserialized into and uploaded from with `from_data`. The crate using the
+    /// bindings needs to depend on `serde` with the `derive` feature
+    pub fn with_serde(mut self, serde: bool) -> Self {
+        self.serde = serde;
+        self
+    }
+
+    /// Format the generated Rust code with `rustfmt`, this is enabled by default and skipped
+    /// when `rustfmt` isn't installed. Code that `rustfmt` can't parse is reported as
+    /// `Error::InvalidGeneratedCode`, disabling this writes the output as-is
+    pub fn with_format(mut self, format: bool) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the visibility of the generated Rust items, or of the module when using
+    /// `Config::with_module`. The default is `Visibility::Pub`
+    pub fn with_visibility(mut self, visibility: Visibility) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Wrap the generated Rust code in a module with this name
+    pub fn with_module(mut self, name: Option<&str>) -> Self {
+        self.module = name.map(String::from);
+        self
+    }
+
+    /// Add `#[allow(dead_code, missing_docs, non_camel_case_types, clippy::all)]` to the
+    /// generated Rust items, or to the module when using `Config::with_module`, so the bindings
+    /// can be included in crates that deny warnings
+    pub fn with_allow_lints(mut self, allow_lints: bool) -> Self {
+        self.allow_lints = allow_lints;
+        self
+    }
+
+    /// Generate the entry point `futhark_name` as `name`, this takes precedence over
+    /// `Config::with_rename_style`
+    pub fn rename_entry(mut self, futhark_name: &str, name: &str) -> Self {
+        self.entry_renames
+            .insert(futhark_name.to_string(), name.to_string());
+        self
+    }
+
+    /// Name the generated Rust type for the Futhark type `futhark_name`, e.g. `"[]f32"` or
+    /// `"state"`, `name` instead
+    pub fn rename_type(mut self, futhark_name: &str, name: &str) -> Self {
+        self.type_renames
+            .insert(futhark_name.to_string(), name.to_string());
+        self
+    }
+
+    /// Convert the names of entry points that aren't renamed with `Config::rename_entry` to
+    /// this style
+    pub fn with_rename_style(mut self, style: RenameStyle) -> Self {
+        self.rename_style = Some(style);
+        self
+    }
+
+    /// Also generate `Array<T, R>`, an alias for the array type with element type `T` and rank
+    /// `R`, and the `GenericArray` trait implemented by the array types, for code that is generic
+    /// over ranks. Arrays of `f16` are left out without the `half` feature. Only supported when
+    /// generating Rust code
+    pub fn with_generic_arrays(mut self, generic_arrays: bool) -> Self {
+        self.generic_arrays = generic_arrays;
+        self
+    }
+
+    /// Also generate `FUTHARK_SOURCE`, containing the source file the program was compiled from.
+    /// Imported files aren't included. Only supported when generating Rust code
+    pub fn with_embedded_source(mut self, embedded_source: bool) -> Self {
+        self.embedded_source = embedded_source;
+        self
+    }
+
+    /// Generate a trait with this name, with a method for each entry point that takes and
+    /// returns values in host memory, and implement it for `Context`. Arrays are passed as
+    /// `(&[T], [i64; N])` and returned as `(Vec<T>, [i64; N])`, entry points using opaque
+    /// types are left out. Only supported when generating Rust code
+    pub fn with_entry_trait(mut self, name: &str) -> Self {
+        self.entry_trait = Some(name.to_string());
+        self
+    }
+
+    /// Only compile the Rust code for the named entry points when the cargo feature `feature` is
+    /// enabled. Types are gated as well when they are only used by gated entry points, a type
+    /// used by entry points with different features is compiled when any of them is enabled. The
+    /// Futhark library itself still contains everything
+    pub fn feature_gate(mut self, feature: &str, entries: &[&str]) -> Self {
+        for name in entries {
+            self.feature_gates
+                .insert(name.to_string(), feature.to_string());
+        }
+        self
+    }
+
+    /// `cfg` attributes for the gated entry points and types of `manifest`, see
+    /// `Config::feature_gate`
+    pub(crate) fn feature_cfgs(
+        &self,
+        manifest: &Manifest,
+    ) -> (BTreeMap<String, String>, BTreeMap<String, String>) {
+        let cfg = |features: BTreeSet<&String>| {
+            let features: Vec<_> = features
+                .iter()
+                .map(|f| format!("feature = {f:?}"))
+                .collect();
+            match features.len() {
+                1 => format!("#[cfg({})]", features[0]),
+                _ => format!("#[cfg(any({}))]", features.join(", ")),
+            }
+        };
+
+        let mut entries = BTreeMap::new();
+        let mut type_features: BTreeMap<&str, Option<BTreeSet<&String>>> = BTreeMap::new();
+        for name in manifest.entry_points.keys() {
+            let feature = self.feature_gates.get(name);
+            if let Some(feature) = feature {
+                entries.insert(name.clone(), cfg(BTreeSet::from([feature])));
+            }
+            let used = manifest.filter_entry_points(|x| x == name).types;
+            for ty in manifest.types.keys().filter(|ty| used.contains_key(*ty)) {
+                let features = type_features
+                    .entry(ty)
+                    .or_insert_with(|| Some(BTreeSet::new()));
+                // A type used by an entry point that isn't gated is always compiled
+                match (feature, features.as_mut()) {
+                    (Some(feature), Some(features)) => {
+                        features.insert(feature);
+                    }
+                    _ => *features = None,
+                }
+            }
+        }
+        let types = type_features
+            .into_iter()
+            .filter_map(|(ty, features)| Some((ty.to_string(), cfg(features?))))
+            .collect();
+        (entries, types)
+    }
+
+    /// Name of the generated function for an entry point
+    pub(crate) fn entry_name(&self, name: &str) -> String {
+        let name = match (self.entry_renames.get(name), self.rename_style) {
+            (Some(name), _) => name.clone(),
+            (None, Some(style)) => style.apply(name),
+            (None, None) => name.to_string(),
+        };
+        sanitize_name(&name)
+    }
+
+    /// Name of the generated Rust type for a Futhark type, `default` unless it is renamed
+    pub(crate) fn type_name(&self, name: &str, default: String) -> String {
+        self.type_renames.get(name).cloned().unwrap_or(default)
+    }
+
+    /// Check that every renamed entry point and type exists in `manifest`
+    fn check_renames(&self, manifest: &Manifest) -> Result<(), Error> {
+        if let Some(name) = self
+            .entry_renames
+            .keys()
+            .find(|name| !manifest.entry_points.contains_key(*name))
+        {
+            return Err(Error::UnknownEntryPoint(name.clone()));
+        }
+        if let Some(name) = self
+            .type_renames
+            .keys()
+            .find(|name| !manifest.types.contains_key(*name))
+        {
+            return Err(Error::UnknownType(name.clone()));
+        }
+        if let Some(name) = self
+            .feature_gates
+            .keys()
+            .find(|name| !manifest.entry_points.contains_key(*name))
+        {
+            return Err(Error::UnknownEntryPoint(name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Only generate code for the named entry points and the types they use
+    pub fn with_entry_points(mut self, names: &[&str]) -> Self {
+        self.entry_points = Some(names.iter().map(|x| x.to_string()).collect());
+        self
+    }
+
+    /// Don't generate code for the named entry points
+    pub fn without_entry_points(mut self, names: &[&str]) -> Self {
+        self.excluded_entry_points = names.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Write a JSON file describing the generated code next to the output file, with
+    /// `.meta.json` appended to its name
+    ///
+    /// This maps each entry point to the generated method along with its parameter and
+    /// return types, and each type in the manifest to the generated type. Currently
+    /// only supported when generating Rust code
+    pub fn with_metadata(mut self, metadata: bool) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Apply the entry point filters to `manifest`, returns `None` if there are no filters
+    fn filter_manifest(&self, manifest: &Manifest) -> Result<Option<Manifest>, Error> {
+        if self.entry_points.is_none() && self.excluded_entry_points.is_empty() {
+            return Ok(None);
+        }
+
+        let names = self.entry_points.iter().flatten();
+        for name in names.chain(&self.excluded_entry_points) {
+            if !manifest.entry_points.contains_key(name) {
+                return Err(Error::UnknownEntryPoint(name.clone()));
+            }
+        }
+
+        Ok(Some(manifest.filter_entry_points(|name| {
+            let included = match &self.entry_points {
+                Some(names) => names.iter().any(|x| x == name),
+                None => true,
+            };
+            included && !self.excluded_entry_points.iter().any(|x| x == name)
+        })))
+    }
 }
 
 pub trait Generate {
     /// Iterates through the manifest and generates code
+    ///
+    /// The generated code only depends on the package and config, and the output file is only
+    /// written when its contents change, so generating the same bindings again keeps its
+    /// modification time
+    ///
+    /// ```
+    /// use futhark_bindgen::manifest::{ElemType, Entry};
+    /// use futhark_bindgen::{Backend, Config, Generate, Manifest, Package, Rust};
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let dir = std::env::temp_dir().join("futhark-bindgen-generate-doctest");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// let manifest = Manifest::new(Backend::C)
+    ///     .with_array_type(ElemType::F32, 1)
+    ///     .with_entry_point("sum", Entry::new("sum").with_input("xs", "[]f32", false).with_output("f32", false));
+    /// std::fs::write(dir.join("sum.json"), serde_json::to_string(&manifest).unwrap()).unwrap();
+    /// let pkg = Package::from_manifest(dir.join("sum.json")).unwrap();
+    ///
+    /// let output = dir.join("sum.rs");
+    /// let generate = || Rust::default().generate(&pkg, &mut Config::new(&output).unwrap());
+    /// generate().unwrap();
+    /// let first = std::fs::read(&output).unwrap();
+    ///
+    /// let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1 << 30);
+    /// std::fs::File::options().write(true).open(&output).unwrap().set_modified(old).unwrap();
+    /// generate().unwrap();
+    /// assert_eq!(std::fs::read(&output).unwrap(), first);
+    /// assert_eq!(std::fs::metadata(&output).unwrap().modified().unwrap(), old);
+    /// ```
     fn generate(&mut self, pkg: &Package, config: &mut Config) -> Result<(), Error> {
+        config.check_renames(&pkg.manifest)?;
+        let mut manifest = config.filter_manifest(&pkg.manifest)?;
+        let current = manifest.as_ref().unwrap_or(&pkg.manifest);
+        let unsupported: Vec<String> = current
+            .entry_points
+            .iter()
+            .filter(|(_, entry)| !self.supports_entry(current, entry))
+            .map(|(name, _)| name.clone())
+            .collect();
+        if !unsupported.is_empty() {
+            for name in &unsupported {
+                crate::warn(&format!(
+                    "Skipping entry point {name}, it uses types that aren't supported by the output language"
+                ));
+            }
+            manifest =
+                Some(current.filter_entry_points(|name| !unsupported.iter().any(|x| x == name)));
+        }
+
+        let filtered;
+        let pkg = match manifest {
+            Some(manifest) => {
+                filtered = Package {
+                    manifest,
+                    ..pkg.clone()
+                };
+                &filtered
+            }
+            None => pkg,
+        };
+
+        let mut names = BTreeMap::new();
+        for name in pkg.manifest.entry_points.keys() {
+            for ident in self.entry_idents(config, name) {
+                if let Some(first) = names.insert(ident.clone(), name) {
+                    return Err(Error::NameCollision {
+                        name: ident,
+                        first: first.clone(),
+                        second: name.clone(),
+                    });
+                }
+            }
+        }
+
         self.bindings(pkg, config)?;
-        for (name, ty) in &pkg.manifest.types {
-            match ty {
+        for name in pkg.manifest.types_in_dependency_order() {
+            match &pkg.manifest.types[name] {
                 manifest::Type::Array(ty) => {
                     self.array_type(pkg, config, name, ty)?;
                 }
@@ -62,8 +618,26 @@ pub trait Generate {
         for (name, entry) in &pkg.manifest.entry_points {
             self.entry(pkg, config, name, entry)?;
         }
-        self.format(&config.output_path)?;
-        Ok(())
+        self.finish(pkg, config)?;
+        let code = String::from_utf8_lossy(&std::mem::take(&mut config.output_file)).into_owned();
+        let code = self.format(&config.output_path, code)?;
+        write_if_changed(&config.output_path, code.as_bytes())
+    }
+
+    /// Returns `false` for entry points that can't be represented in the output language, these
+    /// are skipped with a warning
+    fn supports_entry(&self, _manifest: &Manifest, _entry: &manifest::Entry) -> bool {
+        true
+    }
+
+    /// Name of the generated function for an entry point
+    fn entry_ident(&self, config: &Config, name: &str) -> String {
+        config.entry_name(name)
+    }
+
+    /// Every name generated for an entry point, generation fails if two entry points share one
+    fn entry_idents(&self, config: &Config, name: &str) -> Vec<String> {
+        vec![self.entry_ident(config, name)]
     }
 
     /// Step 1: generate any setup code or low-level bindings
@@ -96,10 +670,16 @@ pub trait Generate {
         entry: &manifest::Entry,
     ) -> Result<(), Error>;
 
-    /// Step 5: Optionally, run any formatting program or post-processing on the output file
-    fn format(&mut self, _output: &std::path::Path) -> Result<(), Error> {
+    /// Step 5: Optionally, write any code collected while generating the previous steps
+    fn finish(&mut self, _pkg: &Package, _config: &mut Config) -> Result<(), Error> {
         Ok(())
     }
+
+    /// Step 6: Optionally, run any formatting program or post-processing on the generated code
+    /// before it is written to `output`
+    fn format(&mut self, _output: &std::path::Path, code: String) -> Result<String, Error> {
+        Ok(code)
+    }
 }
 
 fn rust() -> Box<impl Generate> {
@@ -113,13 +693,14 @@ fn ocaml(config: &Config) -> Box<impl Generate> {
 impl Config {
     /// Automatically detect output language
     pub fn detect(&self) -> Option<Box<dyn Generate>> {
-        match self
-            .output_path
-            .extension()
-            .map(|x| x.to_str().expect("Invalid extension"))
-        {
-            Some("rs") => Some(rust()),
-            Some("ml") => Some(ocaml(self)),
+        self.language(self.output_path.extension()?.to_str()?)
+    }
+
+    /// Select the output language by name or file extension: `rust`, `rs`, `ocaml` or `ml`
+    pub fn language(&self, name: &str) -> Option<Box<dyn Generate>> {
+        match name {
+            "rust" | "rs" => Some(rust()),
+            "ocaml" | "ml" => Some(ocaml(self)),
             _ => None,
         }
     }
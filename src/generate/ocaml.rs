@@ -1,14 +1,37 @@
 use std::io::Write;
 
-use crate::generate::{convert_struct_name, first_uppercase};
+use crate::generate::{
+    convert_struct_name, entry_signature, first_uppercase, opaque_description, sanitize_name,
+    stored_layout, write_if_changed,
+};
 use crate::*;
 
+/// There is no half precision Bigarray kind, `f16` scalars are converted to `float`
+fn uses_f16_array(manifest: &Manifest, t: &str) -> bool {
+    match manifest.types.get(t) {
+        Some(manifest::Type::Array(a)) => matches!(a.elemtype, manifest::ElemType::F16),
+        Some(manifest::Type::Opaque(ty)) => ty
+            .dependencies()
+            .iter()
+            .any(|t| uses_f16_array(manifest, t)),
+        None => false,
+    }
+}
+
+/// OCaml keywords, `_` is appended to entry points with these names
+const KEYWORDS: &str = "\
+    and as assert asr begin class constraint do done downto else end exception external false \
+    for fun function functor if in include inherit initializer land lazy let lor lsl lsr lxor \
+    match method mod module mutable new nonrec object of open or private rec sig struct then \
+    to true try type val virtual when while with";
+
 /// OCaml codegen
 pub struct OCaml {
     typemap: BTreeMap<String, String>,
     ctypes_map: BTreeMap<String, String>,
     ba_map: BTreeMap<String, (String, String)>,
-    mli_file: std::fs::File,
+    mli_path: std::path::PathBuf,
+    mli_file: Vec<u8>,
 }
 
 const OCAML_CTYPES_MAP: &[(&str, &str)] = &[
@@ -20,7 +43,7 @@ const OCAML_CTYPES_MAP: &[(&str, &str)] = &[
     ("u32", "uint32_t"),
     ("i64", "int64_t"),
     ("u64", "uint64_t"),
-    ("f16", ""), // No half type in OCaml
+    ("f16", "f16"), // Converted by the `f16` view in bindings.ml
     ("f32", "float"),
     ("f64", "double"),
     ("bool", "bool"),
@@ -35,7 +58,7 @@ const OCAML_TYPE_MAP: &[(&str, &str)] = &[
     ("i64", "int64"),
     ("u32", "UInt32.t"),
     ("u64", "UInt64.t"),
-    ("f16", ""), // No half type in OCaml
+    ("f16", "float"),
     ("f32", "float"),
     ("f64", "float"),
     ("bool", "bool"),
@@ -92,13 +115,12 @@ impl OCaml {
             .map(|(a, b)| (a.to_string(), b.to_string()))
             .collect();
 
-        let mli_path = config.output_path.with_extension("mli");
-        let mli_file = std::fs::File::create(mli_path)?;
         Ok(OCaml {
             typemap,
             ba_map,
             ctypes_map,
-            mli_file,
+            mli_path: config.output_path.with_extension("mli"),
+            mli_file: Vec::new(),
         })
     }
 
@@ -143,13 +165,25 @@ impl OCaml {
 }
 
 impl Generate for OCaml {
+    /// Values can't start with an uppercase letter, so the first letter is lowercased
+    fn entry_ident(&self, config: &Config, name: &str) -> String {
+        let mut name = config.entry_name(name);
+        if let Some(c) = name.get_mut(0..1) {
+            c.make_ascii_lowercase();
+        }
+        if KEYWORDS.split_whitespace().any(|k| k == name) {
+            name.push('_');
+        }
+        name
+    }
+
     fn bindings(&mut self, pkg: &Package, config: &mut Config) -> Result<(), Error> {
         writeln!(self.mli_file, "(* Generated by futhark-bindgen *)\n")?;
         writeln!(config.output_file, "(* Generated by futhark-bindgen *)\n")?;
 
         let mut generated_foreign_functions = Vec::new();
         match pkg.manifest.backend {
-            Backend::Multicore => {
+            Backend::Multicore | Backend::ISPC => {
                 generated_foreign_functions.push(format!(
                     "  {}",
                     self.foreign_function(
@@ -159,7 +193,7 @@ impl Generate for OCaml {
                     )
                 ));
             }
-            Backend::CUDA | Backend::OpenCL => {
+            Backend::CUDA | Backend::OpenCL | Backend::HIP => {
                 generated_foreign_functions.push(format!(
                     "  {}",
                     self.foreign_function(
@@ -172,15 +206,15 @@ impl Generate for OCaml {
             _ => (),
         }
 
-        for (name, ty) in &pkg.manifest.types {
-            match ty {
+        for name in pkg.manifest.types_in_dependency_order() {
+            match &pkg.manifest.types[name] {
                 manifest::Type::Array(a) => {
                     let elemtype = a.elemtype.to_str().to_string();
                     let ctypes_elemtype = self.get_ctype(&elemtype);
                     let rank = a.rank;
                     let ocaml_name = format!("array_{elemtype}_{rank}d");
-                    self.typemap.insert(name.clone(), ocaml_name.clone());
-                    self.ctypes_map.insert(name.clone(), ocaml_name.clone());
+                    self.typemap.insert(name.to_string(), ocaml_name.clone());
+                    self.ctypes_map.insert(name.to_string(), ocaml_name.clone());
                     let elem_ptr = format!("ptr {ctypes_elemtype}");
                     generated_foreign_functions.push(format!(
                         "  let {ocaml_name} = typedef (ptr void) \"{ocaml_name}\""
@@ -222,8 +256,10 @@ impl Generate for OCaml {
                         ocaml_name = format!("type_{ocaml_name}");
                     }
 
-                    self.typemap
-                        .insert(name.clone(), format!("{}.t", first_uppercase(&ocaml_name)));
+                    self.typemap.insert(
+                        name.to_string(),
+                        format!("{}.t", first_uppercase(&ocaml_name)),
+                    );
                     self.ctypes_map.insert(name.to_string(), ocaml_name.clone());
                     generated_foreign_functions.push(format!(
                         "  let {ocaml_name} = typedef (ptr void) \"{futhark_name}\""
@@ -234,6 +270,22 @@ impl Generate for OCaml {
                         "  {}",
                         self.foreign_function(free_fn, "int", vec!["context", &ocaml_name])
                     ));
+                    generated_foreign_functions.push(format!(
+                        "  {}",
+                        self.foreign_function(
+                            &ty.ops.store,
+                            "int",
+                            vec!["context", &ocaml_name, "ptr (ptr void)", "ptr size_t"]
+                        )
+                    ));
+                    generated_foreign_functions.push(format!(
+                        "  {}",
+                        self.foreign_function(
+                            &ty.ops.restore,
+                            &ocaml_name,
+                            vec!["context", "ptr void"]
+                        )
+                    ));
 
                     let record = match &ty.record {
                         Some(r) => r,
@@ -300,13 +352,13 @@ impl Generate for OCaml {
         writeln!(self.mli_file, include_str!("templates/ocaml/bindings.mli"))?;
 
         let (extra_param, extra_line, extra_mli) = match pkg.manifest.backend {
-            Backend::Multicore => (
+            Backend::Multicore | Backend::ISPC => (
                 "?(num_threads = 0)",
                 "    Bindings.futhark_context_config_set_num_threads config num_threads;",
                 "?num_threads:int ->",
             ),
 
-            Backend::CUDA | Backend::OpenCL => (
+            Backend::CUDA | Backend::OpenCL | Backend::HIP => (
                 "?device",
                 "    Option.iter (Bindings.futhark_context_config_set_device config) device;",
                 "?device:string ->",
@@ -323,7 +375,8 @@ impl Generate for OCaml {
         writeln!(
             self.mli_file,
             include_str!("templates/ocaml/context.mli"),
-            extra_mli = extra_mli
+            extra_mli = extra_mli,
+            backend = pkg.manifest.backend.to_str(),
         )?;
 
         Ok(())
@@ -347,6 +400,7 @@ impl Generate for OCaml {
 
         let (ocaml_elemtype, ba_elemtype) = self.get_ba_type(&elemtype);
         let ocaml_ctype = self.get_ctype(&elemtype);
+        let is_bool = matches!(ty.elemtype, manifest::ElemType::Bool);
 
         writeln!(
             config.output_file,
@@ -359,14 +413,25 @@ impl Generate for OCaml {
             ba_elemtype = ba_elemtype,
             ba_kind = ba_kind(&ba_elemtype),
             ocaml_ctype = ocaml_ctype,
+            extra = if is_bool {
+                include_str!("templates/ocaml/array_bool.ml")
+            } else {
+                ""
+            },
         )?;
 
         writeln!(
             self.mli_file,
             include_str!("templates/ocaml/array.mli"),
+            name = name,
             module_name = module_name,
             ocaml_elemtype = ocaml_elemtype,
             ba_elemtype = ba_elemtype,
+            extra = if is_bool {
+                include_str!("templates/ocaml/array_bool.mli")
+            } else {
+                ""
+            },
         )?;
 
         Ok(())
@@ -374,7 +439,7 @@ impl Generate for OCaml {
 
     fn opaque_type(
         &mut self,
-        _pkg: &Package,
+        pkg: &Package,
         config: &mut Config,
         name: &str,
         ty: &manifest::OpaqueType,
@@ -396,13 +461,43 @@ impl Generate for OCaml {
         writeln!(config.output_file, "module {module_name} = struct")?;
         writeln!(self.mli_file, "module {module_name} : sig")?;
 
+        // Without the layout the restore function can read past the end of the input
+        let (layout, restore_doc) = match stored_layout(&pkg.manifest, name) {
+            Some(layout) => {
+                let values: Vec<_> = layout
+                    .iter()
+                    .map(|(elemtype, rank)| format!("({elemtype:?}, {rank})"))
+                    .collect();
+                let values = format!("(Some [{}])", values.join("; "));
+                (
+                    values,
+                    "raises [Error InvalidData] if the\n      \
+                    input isn't a stored value of this type",
+                )
+            }
+            None => (
+                "None".to_string(),
+                "which must be a stored value of\n      \
+                this type. The manifest doesn't describe how the type is stored, so other input \
+                is\n      only partially checked and can make Futhark read past its end",
+            ),
+        };
+
         writeln!(
             config.output_file,
             include_str!("templates/ocaml/opaque.ml"),
             free_fn = free_fn,
+            store_fn = ty.ops.store,
+            restore_fn = ty.ops.restore,
             name = ocaml_name,
+            stored_layout = layout,
+        )?;
+        writeln!(
+            self.mli_file,
+            include_str!("templates/ocaml/opaque.mli"),
+            description = opaque_description(name, ty),
+            restore_doc = restore_doc,
         )?;
-        writeln!(self.mli_file, include_str!("templates/ocaml/opaque.mli"),)?;
 
         let record = match &ty.record {
             Some(r) => r,
@@ -418,17 +513,18 @@ impl Generate for OCaml {
         let mut new_arg_types = Vec::new();
         for f in record.fields.iter() {
             let t = self.get_type(&f.r#type);
+            let name = sanitize_name(&f.name);
 
-            new_params.push(format!("field{}", f.name));
+            new_params.push(format!("field{name}"));
 
             if type_is_array(&t) {
-                new_call_args.push(format!("(get_ptr field{})", f.name));
+                new_call_args.push(format!("(get_ptr field{name})"));
                 new_arg_types.push(format!("{}.t", first_uppercase(&t)));
             } else if type_is_opaque(&t) {
-                new_call_args.push(format!("(get_opaque_ptr field{})", f.name));
+                new_call_args.push(format!("(get_opaque_ptr field{name})"));
                 new_arg_types.push(t.to_string());
             } else {
-                new_call_args.push(format!("field{}", f.name));
+                new_call_args.push(format!("field{name}"));
                 new_arg_types.push(t.to_string());
             }
         }
@@ -449,20 +545,26 @@ impl Generate for OCaml {
 
         for f in record.fields.iter() {
             let t = self.get_type(&f.r#type);
-            let name = &f.name;
+            let name = sanitize_name(&f.name);
             let project = &f.project;
 
-            let (out, out_type) = if type_is_opaque(&t) {
+            // Scalar fields are read into host memory, which is only written after a sync
+            let (out, out_type, sync) = if type_is_opaque(&t) {
                 let call = t.replace(".t", ".of_ptr");
-                (format!("{call} t.opaque_ctx !@out"), t.to_string())
+                (
+                    format!("{call} t.opaque_ctx !@out"),
+                    t.to_string(),
+                    "auto_sync",
+                )
             } else if type_is_array(&t) {
                 let array = first_uppercase(&t);
                 (
                     format!("{array}.of_ptr t.opaque_ctx !@out"),
                     format!("{}.t", first_uppercase(&t)),
+                    "auto_sync",
                 )
             } else {
-                ("!@out".to_string(), t.to_string())
+                ("!@out".to_string(), t.to_string(), "sync")
             };
 
             let alloc_type = if type_is_array(&t) {
@@ -479,7 +581,8 @@ impl Generate for OCaml {
                 name = name,
                 s = alloc_type,
                 project = project,
-                out = out
+                out = out,
+                sync = sync,
             )?;
             writeln!(
                 self.mli_file,
@@ -495,6 +598,12 @@ impl Generate for OCaml {
         Ok(())
     }
 
+    fn supports_entry(&self, manifest: &Manifest, entry: &manifest::Entry) -> bool {
+        let inputs = entry.inputs.iter().map(|x| x.r#type.as_str());
+        let outputs = entry.outputs.iter().map(|x| x.r#type.as_str());
+        !inputs.chain(outputs).any(|t| uses_f16_array(manifest, t))
+    }
+
     fn entry(
         &mut self,
         _pkg: &Package,
@@ -502,7 +611,7 @@ impl Generate for OCaml {
         name: &str,
         entry: &manifest::Entry,
     ) -> Result<(), Error> {
-        let mut arg_types = Vec::new();
+        let mut arg_types = vec!["Context.t".to_string()];
         let mut return_type = Vec::new();
         let mut entry_params = Vec::new();
         let mut call_args = Vec::new();
@@ -574,6 +683,7 @@ impl Generate for OCaml {
             config.output_file,
             include_str!("templates/ocaml/entry.ml"),
             name = name,
+            fn_name = self.entry_ident(config, name),
             entry_params = entry_params.join(" "),
             out_decl = out_decl.join("\n"),
             call_args = call_args.join(" "),
@@ -589,10 +699,16 @@ impl Generate for OCaml {
             self.mli_file,
             include_str!("templates/ocaml/entry.mli"),
             name = name,
+            fn_name = self.entry_ident(config, name),
+            signature = entry_signature(name, entry),
             arg_types = arg_types.join(" -> "),
             return_type = return_type,
         )?;
 
         Ok(())
     }
+    fn format(&mut self, _output: &std::path::Path, code: String) -> Result<String, Error> {
+        write_if_changed(&self.mli_path, &std::mem::take(&mut self.mli_file))?;
+        Ok(code)
+    }
 }
@@ -1,22 +1,124 @@
-use crate::generate::{convert_struct_name, first_uppercase};
+use crate::generate::{
+    convert_struct_name, entry_signature, first_uppercase, opaque_description, sanitize_name,
+    stored_layout, write_if_changed,
+};
+use crate::sha256::sha256_hex;
 use crate::*;
 use std::io::Write;
 
 /// Rust codegen
 pub struct Rust {
     typemap: BTreeMap<String, String>,
+    symbol_prefix: Option<String>,
+    format: bool,
+    metadata: Option<Metadata>,
+    sys: String,
+
+    /// Generated types with the Futhark type, field of their pointer and `cfg` attribute
+    wrappers: Vec<(String, String, &'static str, String)>,
+
+    /// Signatures and bodies of the methods of the entry point trait
+    trait_methods: Vec<(String, String)>,
+
+    /// Variants of `BatchResult`
+    batch_variants: Vec<String>,
+
+    /// `cfg` attributes of feature gated entry points and types, see `Config::feature_gate`
+    entry_cfgs: BTreeMap<String, String>,
+    type_cfgs: BTreeMap<String, String>,
+
+    /// `cfg` attribute added to the items of the code currently being generated
+    cfg: Option<String>,
+}
+
+/// Rust keywords, entry points with these names are generated as raw identifiers
+const KEYWORDS: &str = "\
+    abstract as async await become box break const continue do dyn else enum extern false \
+    final fn for gen if impl in let loop macro match mod move mut override priv pub ref return \
+    static struct trait true try type typeof unsafe unsized use virtual where while yield";
+
+/// Keywords that can't be raw identifiers, `_` is appended to them instead
+const RESERVED: &[&str] = &["self", "Self", "super", "crate", "_"];
+
+/// Turn a sanitized name into a valid identifier, without the `r#` prefix so it can be used
+/// as part of other names
+pub(crate) fn rust_ident(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Name of the `_async` variant of an entry point, `name` is the result of `rust_ident`
+fn async_ident(name: &str) -> String {
+    format!("{}_async", name.trim_end_matches('_'))
+}
+
+/// UpperCamelCase version of the result of `rust_ident`, for the variants of `BatchResult`
+fn camel_ident(name: &str) -> String {
+    let camel: String = name.split('_').map(first_uppercase).collect();
+    rust_ident(&camel)
+}
+
+/// Add the `r#` prefix to keywords
+pub(crate) fn raw_ident(name: &str) -> String {
+    if KEYWORDS.split_whitespace().any(|k| k == name) {
+        format!("r#{name}")
+    } else {
+        name.to_string()
+    }
+}
+
+/// Description of the generated code, written next to the output file when
+/// `Config::with_metadata` is enabled
+#[derive(Default, serde::Serialize)]
+struct Metadata {
+    /// Entry point name to generated method
+    entry_points: BTreeMap<String, EntryMetadata>,
+
+    /// Futhark type name to generated Rust type
+    types: BTreeMap<String, String>,
+}
+
+#[derive(serde::Serialize)]
+struct EntryMetadata {
+    /// Name of the method on `Context`
+    function: String,
+
+    /// Rust parameter types
+    params: Vec<String>,
+
+    /// Rust return type, not including the `Result`
+    returns: String,
 }
 
-fn type_is_array(t: &str) -> bool {
-    t.contains("ArrayF") || t.contains("ArrayI") || t.contains("ArrayU") || t.contains("ArrayB")
+fn type_is_array(a: &str) -> bool {
+    a.starts_with("futhark_") && !type_is_opaque(a)
 }
 
 fn type_is_opaque(a: &str) -> bool {
     a.contains("futhark_opaque_")
 }
 
-// Rust `f16` codgen requires the `half` crate
+// Rust `f16` codgen requires the `half` crate, without the `half` feature `f16` scalars are
+// passed as `F16Bits` and the elements of arrays as their `u16` bit patterns
+#[cfg(feature = "half")]
 const RUST_TYPE_MAP: &[(&str, &str)] = &[("f16", "half::f16")];
+#[cfg(not(feature = "half"))]
+const RUST_TYPE_MAP: &[(&str, &str)] = &[("f16", "F16Bits")];
+
+/// Whether `t` is an `f16` scalar or an opaque type containing one
+fn uses_f16_scalar(manifest: &Manifest, t: &str) -> bool {
+    match manifest.types.get(t) {
+        Some(manifest::Type::Array(_)) => false,
+        Some(manifest::Type::Opaque(ty)) => ty
+            .dependencies()
+            .iter()
+            .any(|t| uses_f16_scalar(manifest, t)),
+        None => t == "f16",
+    }
+}
 
 impl Default for Rust {
     fn default() -> Self {
@@ -24,7 +126,19 @@ impl Default for Rust {
             .iter()
             .map(|(a, b)| (a.to_string(), b.to_string()))
             .collect();
-        Rust { typemap }
+        Rust {
+            typemap,
+            symbol_prefix: None,
+            format: true,
+            metadata: None,
+            sys: String::new(),
+            wrappers: Vec::new(),
+            trait_methods: Vec::new(),
+            batch_variants: Vec::new(),
+            entry_cfgs: BTreeMap::new(),
+            type_cfgs: BTreeMap::new(),
+            cfg: None,
+        }
     }
 }
 
@@ -35,7 +149,556 @@ struct ArrayInfo {
     elem: String,
 }
 
+/// A backend specific setting of the generated `Options`
+struct BackendOption {
+    field: &'static str,
+    default: &'static str,
+    method: &'static str,
+    configure: &'static str,
+    extern_fn: &'static str,
+}
+
+const BACKEND_OPTION_THREADS: BackendOption = BackendOption {
+    field: "num_threads: std::option::Option<u32>,",
+    default: "num_threads: None,",
+    method: "/// Set the number of worker threads, 0 uses one thread per core. When this isn't set
+    /// the Futhark default is used
+    pub fn num_threads(mut self, n: u32) -> Options { self.num_threads = Some(n); self }
+
+    #[deprecated(note = \"use `num_threads` instead\")]
+    pub fn threads(self, n: u32) -> Options { self.num_threads(n) }",
+    configure: "if let Some(n) = options.num_threads { futhark_context_config_set_num_threads(config, n as std::os::raw::c_int); }",
+    extern_fn: "pub fn futhark_context_config_set_num_threads(_: *mut futhark_context_config, _: std::os::raw::c_int);",
+};
+
+const BACKEND_OPTION_DEVICE: BackendOption = BackendOption {
+    field: "device: std::option::Option<std::ffi::CString>,",
+    default: "device: None,",
+    method: "/// Use the first device whose name contains `s`
+    pub fn device(mut self, s: impl AsRef<str>) -> Options { self.device = self.cstring(s.as_ref()); self }",
+    configure: "if let Some(d) = &options.device { futhark_context_config_set_device(config, d.as_ptr()); }",
+    extern_fn: "pub fn futhark_context_config_set_device(_: *mut futhark_context_config, _: *const std::os::raw::c_char);",
+};
+
+const BACKEND_OPTION_GROUP_SIZE: BackendOption = BackendOption {
+    field: "default_group_size: std::option::Option<u32>,",
+    default: "default_group_size: None,",
+    method: "/// Set the default number of threads in a workgroup
+    pub fn default_group_size(mut self, n: u32) -> Options { self.default_group_size = Some(n); self }",
+    configure: "if let Some(n) = options.default_group_size { futhark_context_config_set_default_group_size(config, n as std::os::raw::c_int); }",
+    extern_fn: "pub fn futhark_context_config_set_default_group_size(_: *mut futhark_context_config, _: std::os::raw::c_int);",
+};
+
+const BACKEND_OPTION_NUM_GROUPS: BackendOption = BackendOption {
+    field: "default_num_groups: std::option::Option<u32>,",
+    default: "default_num_groups: None,",
+    method: "/// Set the default number of workgroups
+    pub fn default_num_groups(mut self, n: u32) -> Options { self.default_num_groups = Some(n); self }",
+    configure: "if let Some(n) = options.default_num_groups { futhark_context_config_set_default_num_groups(config, n as std::os::raw::c_int); }",
+    extern_fn: "pub fn futhark_context_config_set_default_num_groups(_: *mut futhark_context_config, _: std::os::raw::c_int);",
+};
+
+const BACKEND_OPTION_PLATFORM: BackendOption = BackendOption {
+    field: "platform: std::option::Option<std::ffi::CString>,",
+    default: "platform: None,",
+    method: "/// Use the first OpenCL platform whose name contains `s`
+    pub fn platform(mut self, s: impl AsRef<str>) -> Options { self.platform = self.cstring(s.as_ref()); self }",
+    configure: "if let Some(p) = &options.platform { futhark_context_config_set_platform(config, p.as_ptr()); }",
+    extern_fn: "pub fn futhark_context_config_set_platform(_: *mut futhark_context_config, _: *const std::os::raw::c_char);",
+};
+
+const BACKEND_OPTION_CUDA_DEVICE: BackendOption = BackendOption {
+    field: "",
+    default: "",
+    method: "/// Use the CUDA device with index `n`, e.g. `cuda_device(1)` selects the second GPU
+    pub fn cuda_device(self, n: usize) -> Options { self.device(format!(\"#{n}\")) }",
+    configure: "",
+    extern_fn: "",
+};
+
+const BACKEND_OPTION_NVRTC: BackendOption = BackendOption {
+    field: "nvrtc_options: Vec<std::ffi::CString>,",
+    default: "nvrtc_options: Vec::new(),",
+    method: "/// Pass an option to NVRTC when compiling the kernels, this can be called multiple times
+    pub fn nvrtc_option(mut self, s: impl AsRef<str>) -> Options { let o = self.cstring(s.as_ref()); self.nvrtc_options.extend(o); self }",
+    configure: "for o in &options.nvrtc_options { futhark_context_config_add_nvrtc_option(config, o.as_ptr()); }",
+    extern_fn: "pub fn futhark_context_config_add_nvrtc_option(_: *mut futhark_context_config, _: *const std::os::raw::c_char);",
+};
+
+const BACKEND_OPTION_DUMP_PTX: BackendOption = BackendOption {
+    field: "dump_ptx: std::option::Option<std::ffi::CString>,",
+    default: "dump_ptx: None,",
+    method: "/// Write the PTX of the compiled kernels to a file
+    pub fn dump_ptx(mut self, path: impl AsRef<std::path::Path>) -> Options { self.dump_ptx = self.path_cstring(path.as_ref()); self }",
+    configure: "if let Some(f) = &options.dump_ptx { futhark_context_config_dump_ptx_to(config, f.as_ptr()); }",
+    extern_fn: "pub fn futhark_context_config_dump_ptx_to(_: *mut futhark_context_config, _: *const std::os::raw::c_char);",
+};
+
+const BACKEND_OPTION_LOAD_PTX: BackendOption = BackendOption {
+    field: "load_ptx: std::option::Option<std::ffi::CString>,",
+    default: "load_ptx: None,",
+    method: "/// Load the kernels from a PTX file instead of compiling them
+    pub fn load_ptx(mut self, path: impl AsRef<std::path::Path>) -> Options { self.load_ptx = self.path_cstring(path.as_ref()); self }",
+    configure: "if let Some(f) = &options.load_ptx { futhark_context_config_load_ptx_from(config, f.as_ptr()); }",
+    extern_fn: "pub fn futhark_context_config_load_ptx_from(_: *mut futhark_context_config, _: *const std::os::raw::c_char);",
+};
+
+/// Reports tuning file entries that don't belong to the program, the variable `name` holds
+/// the parameter name
+#[cfg(feature = "log")]
+const WARN_UNKNOWN_TUNING_PARAM: &str =
+    "log::warn!(target: \"futhark\", \"Skipping unknown tuning parameter {name}\");";
+#[cfg(not(feature = "log"))]
+const WARN_UNKNOWN_TUNING_PARAM: &str = "";
+
+/// Sync after reading a value into host memory, which is only written once the copy from the
+/// device has finished
+const SYNC_HOST: &str = "self.ctx.sync()?;";
+
+/// Sync after a call whose outputs stay on the device
+const SYNC_DEVICE: &str = "self.ctx.auto_sync()?;";
+
+/// Rest of the `restore` documentation when the stored form of the type is known
+const RESTORE_CHECKED: &str = ", input that isn't a stored value of this type is
+    /// rejected with `Error::InvalidData`";
+
+/// Rest of the `restore` documentation when the manifest doesn't describe the stored form
+const RESTORE_SAFETY: &str = "
+    ///
+    /// # Safety
+    /// `bytes` must have been returned by `store` on a value of this type. The manifest doesn't
+    /// describe how the type is stored, so other input is only partially checked and can make
+    /// Futhark read past its end";
+
+/// Hash of the generated C code, or of the manifest when the C code isn't available
+fn program_hash(pkg: &Package) -> String {
+    let mut data = std::fs::read(&pkg.c_file).unwrap_or_default();
+    data.extend(serde_json::to_vec(&pkg.manifest).unwrap_or_default());
+    crate::sha256::sha256_hex(&data)
+}
+
+/// Add a `link_name` attribute to every extern function declared in `src`
+fn add_link_names(src: &str, prefix: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    while let Some(i) = rest.find("pub fn futhark_") {
+        let (before, start) = rest.split_at(i);
+        let name_start = &start["pub fn ".len()..];
+        let len = name_start
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(name_start.len());
+        let name = &name_start[..len];
+        out.push_str(before);
+        out.push_str(&format!("#[link_name = \"{prefix}{name}\"] pub fn {name}"));
+        rest = &name_start[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Attribute added by `Config::with_allow_lints`
+const ALLOW_LINTS: &str = "#[allow(dead_code, missing_docs, non_camel_case_types, clippy::all)]";
+
+/// Start of the lines declaring top-level items in the templates
+const ITEM_KEYWORDS: &[&str] = &[
+    "pub ",
+    "impl",
+    "unsafe impl",
+    "fn ",
+    "const ",
+    "struct ",
+    "enum ",
+    "mod ",
+    "use ",
+];
+
+/// Add `cfg` to the top-level items in `code`, and set their visibility and add `ALLOW_LINTS` to
+/// them when the code isn't wrapped in a module
+fn adjust_items(code: &str, config: &Config, cfg: Option<&str>) -> String {
+    let top_level = config.module.is_none();
+    let vis = config.visibility.to_str();
+    let mut out = String::with_capacity(code.len());
+    for line in code.lines() {
+        if ITEM_KEYWORDS.iter().any(|k| line.starts_with(k)) {
+            if let Some(cfg) = cfg {
+                out.push_str(cfg);
+                out.push('\n');
+            }
+            if config.allow_lints && top_level {
+                out.push_str(ALLOW_LINTS);
+                out.push('\n');
+            }
+        }
+        match line.strip_prefix("pub ").filter(|_| top_level) {
+            Some(rest) => {
+                out.push_str(vis);
+                out.push_str(rest);
+            }
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Parse the `name=value` lines of a tuning file, blank lines are skipped
+fn parse_tuning_file<'a>(
+    path: &std::path::Path,
+    src: &'a str,
+) -> Result<Vec<(&'a str, usize)>, Error> {
+    src.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.split_once('=')
+                .and_then(|(name, value)| Some((name.trim(), value.trim().parse().ok()?)))
+                .ok_or_else(|| Error::InvalidTuningFile {
+                    path: path.to_path_buf(),
+                    line: line.to_string(),
+                })
+        })
+        .collect()
+}
+
 impl Rust {
+    /// Write a filled in template, the C declarations following the `// sys` line are collected
+    /// into the `sys` module
+    fn emit(&mut self, config: &mut Config, code: String) -> Result<(), Error> {
+        let (code, sys) = code.split_once("\n// sys\n").unwrap_or((&code, ""));
+        let code = adjust_items(code, config, self.cfg.as_deref());
+        writeln!(config.output_file, "{code}")?;
+        self.sys.push_str(sys);
+        self.sys.push('\n');
+        Ok(())
+    }
+
+    /// Add the host memory version of an entry point to the entry point trait, returns `false`
+    /// if it uses a type that can't be represented in host memory
+    fn trait_method(
+        &mut self,
+        pkg: &Package,
+        name: &str,
+        fn_name: &str,
+        entry: &manifest::Entry,
+    ) -> bool {
+        let mut params = Vec::new();
+        let mut uploads = Vec::new();
+        let mut call_args = Vec::new();
+        let mut return_types = Vec::new();
+        let mut downloads = Vec::new();
+
+        // Element type, `bits` suffix and rank of an array type
+        let array = |t: &str| match pkg.manifest.types.get(t) {
+            Some(manifest::Type::Array(a)) => {
+                let elemtype = a.elemtype.to_str();
+                if elemtype == "f16" && !cfg!(feature = "half") {
+                    Some(("u16".to_string(), "_bits", a.rank))
+                } else {
+                    Some((Self::get_type(&self.typemap, elemtype), "", a.rank))
+                }
+            }
+            _ => None,
+        };
+
+        for (i, input) in entry.inputs.iter().enumerate() {
+            let a = Self::get_type(&self.typemap, &input.r#type);
+            let t = Self::get_type(&self.typemap, &a);
+            let var = format!("input{i}");
+            if type_is_opaque(&a) {
+                return false;
+            } else if let Some((elem, bits, rank)) = array(&input.r#type) {
+                params.push(format!("{var}: (&[{elem}], [i64; {rank}])"));
+                uploads.push(format!(
+                    "let {var} = {t}::new{bits}(self, {var}.1, {var}.0)?;"
+                ));
+                call_args.push(if input.unique { var } else { format!("&{var}") });
+            } else {
+                params.push(format!("{var}: {t}"));
+                call_args.push(var);
+            }
+        }
+
+        for (i, output) in entry.outputs.iter().enumerate() {
+            let a = Self::get_type(&self.typemap, &output.r#type);
+            let t = Self::get_type(&self.typemap, &a);
+            let var = format!("out{i}");
+            if type_is_opaque(&a) {
+                return false;
+            } else if let Some((elem, bits, rank)) = array(&output.r#type) {
+                return_types.push(format!("(Vec<{elem}>, [i64; {rank}])"));
+                downloads.push(format!("({var}.get{bits}()?, *{var}.shape())"));
+            } else {
+                return_types.push(t);
+                downloads.push(var);
+            }
+        }
+
+        let outputs: Vec<_> = (0..entry.outputs.len())
+            .map(|i| format!("out{i}"))
+            .collect();
+        let (return_type, outputs, downloads) = match entry.outputs.len() {
+            1 => (return_types.join(""), outputs.join(""), downloads.join("")),
+            _ => (
+                format!("({})", return_types.join(", ")),
+                format!("({})", outputs.join(", ")),
+                format!("({})", downloads.join(", ")),
+            ),
+        };
+        let signature = format!(
+            "fn {fn_name}(&self, {}) -> std::result::Result<{return_type}, Error>",
+            params.join(", ")
+        );
+        let mut body = uploads;
+        body.push(format!(
+            "let {outputs} = Context::{fn_name}(self, {})?;",
+            call_args.join(", ")
+        ));
+        body.push(format!("Ok({downloads})"));
+        let doc = format!("/// Entry point: {name}, see `Context::{fn_name}`");
+        let cfg = self
+            .cfg
+            .as_ref()
+            .map(|c| format!("{c}\n    "))
+            .unwrap_or_default();
+        self.trait_methods.push((
+            format!("{doc}\n    {cfg}{signature}"),
+            format!(
+                "{cfg}{signature} {{\n        {}\n    }}",
+                body.join("\n        ")
+            ),
+        ));
+        true
+    }
+
+    fn sum_type(
+        &mut self,
+        config: &mut Config,
+        rust_type: &str,
+        futhark_type: &str,
+        sum: &manifest::Sum,
+    ) -> Result<(), Error> {
+        let mut variants = Vec::new();
+        let mut variant_arms = Vec::new();
+        for (i, variant) in sum.variants.iter().enumerate() {
+            let fn_name = variant
+                .name
+                .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+            let ident = rust_ident(&first_uppercase(&fn_name));
+            variants.push(format!("{ident},"));
+            variant_arms.push(format!("{i} => {rust_type}Variant::{ident},"));
+
+            let mut new_params = Vec::new();
+            let mut new_call_args = Vec::new();
+            let mut construct_extern_params = Vec::new();
+            let mut out_decl = Vec::new();
+            let mut destruct_call_args = Vec::new();
+            let mut destruct_extern_params = Vec::new();
+            let mut payload_types = Vec::new();
+            let mut payload = Vec::new();
+            let mut sync = SYNC_DEVICE;
+            for (j, p) in variant.payload.iter().enumerate() {
+                let a = Self::get_type(&self.typemap, p);
+                let t = Self::get_type(&self.typemap, &a);
+                let name = format!("v{j}");
+
+                if type_is_opaque(&a) || type_is_array(&a) {
+                    let field = if type_is_opaque(&a) { "data" } else { "ptr" };
+                    new_params.push(format!("{name}: &{t}"));
+                    new_call_args.push(format!("{name}.{field}"));
+                    construct_extern_params.push(format!("{name}: *const {a},"));
+                    destruct_extern_params.push(format!("{name}: *mut *mut {a},"));
+                    payload.push(format!("{t}::from_ptr(&self.ctx, {name}.assume_init())"));
+                } else {
+                    new_params.push(format!("{name}: {t}"));
+                    new_call_args.push(name.clone());
+                    construct_extern_params.push(format!("{name}: {a},"));
+                    destruct_extern_params.push(format!("{name}: *mut {a},"));
+                    payload.push(format!("{name}.assume_init()"));
+                    sync = SYNC_HOST;
+                }
+                out_decl.push(format!("let mut {name} = std::mem::MaybeUninit::zeroed();"));
+                destruct_call_args.push(format!("{name}.as_mut_ptr(), "));
+                payload_types.push(t);
+            }
+
+            let (payload_type, payload) = match payload.len() {
+                1 => (payload_types.join(", "), payload.join(", ")),
+                _ => (
+                    format!("({})", payload_types.join(", ")),
+                    format!("({})", payload.join(", ")),
+                ),
+            };
+
+            self.emit(
+                config,
+                format!(
+                    include_str!("templates/rust/sum_variant.rs"),
+                    rust_type = rust_type,
+                    futhark_type = futhark_type,
+                    variant_name = variant.name,
+                    variant_fn_name = fn_name,
+                    variant_ident = ident,
+                    construct_fn = variant.construct,
+                    destruct_fn = variant.destruct,
+                    new_params = new_params.join(", "),
+                    new_call_args = new_call_args.join(", "),
+                    construct_extern_params = construct_extern_params.join("\n"),
+                    out_decl = out_decl.join("\n"),
+                    destruct_call_args = destruct_call_args.join(""),
+                    destruct_extern_params = destruct_extern_params.join("\n"),
+                    payload_type = payload_type,
+                    payload = payload,
+                    sync = sync,
+                ),
+            )?;
+        }
+
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/sum.rs"),
+                rust_type = rust_type,
+                futhark_type = futhark_type,
+                variant_fn = sum.variant,
+                variants = variants.join("\n"),
+                variant_arms = variant_arms.join("\n"),
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn opaque_array_type(
+        &mut self,
+        config: &mut Config,
+        rust_type: &str,
+        futhark_type: &str,
+        array: &manifest::OpaqueArray,
+    ) -> Result<(), Error> {
+        let elem_futhark_type = Self::get_type(&self.typemap, &array.elemtype);
+        let elem_rust_type = Self::get_type(&self.typemap, &elem_futhark_type);
+
+        let index_params: Vec<_> = (0..array.rank).map(|i| format!("i{i}: i64")).collect();
+        let index_args: Vec<_> = (0..array.rank).map(|i| format!("i{i}")).collect();
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/opaque_array.rs"),
+                rust_type = rust_type,
+                futhark_type = futhark_type,
+                elem_rust_type = elem_rust_type,
+                elem_futhark_type = elem_futhark_type,
+                rank = array.rank,
+                shape_fn = array.shape,
+                index_fn = array.index,
+                index_params = index_params.join(", "),
+                index_args = index_args.join(", "),
+                index_extern_params = index_params.join(", "),
+            ),
+        )?;
+
+        if let Some(new_fn) = &array.new {
+            let dim_args: Vec<_> = (0..array.rank).map(|i| format!("dims[{i}]")).collect();
+            let new_dim_params: Vec<_> = (0..array.rank).map(|i| format!("dim{i}: i64")).collect();
+            self.emit(
+                config,
+                format!(
+                    include_str!("templates/rust/opaque_array_new.rs"),
+                    rust_type = rust_type,
+                    futhark_type = futhark_type,
+                    elem_rust_type = elem_rust_type,
+                    elem_futhark_type = elem_futhark_type,
+                    rank = array.rank,
+                    new_fn = new_fn,
+                    dim_args = dim_args.join(", "),
+                    new_dim_params = new_dim_params.join(", "),
+                ),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn record_type(
+        &mut self,
+        config: &mut Config,
+        rust_type: &str,
+        futhark_type: &str,
+        new_fn: &str,
+        fields: &[manifest::Field],
+    ) -> Result<(), Error> {
+        let mut new_call_args = vec![];
+        let mut new_params = vec![];
+        let mut new_extern_params = vec![];
+        for field in fields.iter() {
+            let field_name = sanitize_name(&field.name);
+
+            // Build new function
+            let a = Self::get_type(&self.typemap, &field.r#type);
+            let t = Self::get_type(&self.typemap, &a);
+
+            let u = if type_is_array(&a) || type_is_opaque(&a) {
+                format!("&{t}")
+            } else {
+                t.to_string()
+            };
+
+            if type_is_opaque(&a) {
+                new_call_args.push(format!("field{field_name}.data"));
+                new_extern_params.push(format!("field{field_name}: *const {a}"));
+            } else if type_is_array(&a) {
+                new_call_args.push(format!("field{field_name}.ptr"));
+                new_extern_params.push(format!("field{field_name}: *const {a}"));
+            } else {
+                new_call_args.push(format!("field{field_name}"));
+                new_extern_params.push(format!("field{field_name}: {a}"));
+            }
+
+            new_params.push(format!("field{field_name}: {u}"));
+
+            // Implement get function
+
+            // If the output type is an array or opaque type then we need to wrap the return value
+            let (output, futhark_field_type, sync) = if type_is_opaque(&a) || type_is_array(&a) {
+                (
+                    format!("Ok({t}::from_ptr(&self.ctx, out))"),
+                    format!("*mut {a}"),
+                    SYNC_DEVICE,
+                )
+            } else {
+                ("Ok(out)".to_string(), a, SYNC_HOST)
+            };
+
+            self.emit(
+                config,
+                format!(
+                    include_str!("templates/rust/record_project.rs"),
+                    project_fn = field.project,
+                    rust_type = rust_type,
+                    futhark_type = futhark_type,
+                    field_name = field_name,
+                    futhark_field_type = futhark_field_type,
+                    rust_field_type = t,
+                    output = output,
+                    sync = sync,
+                ),
+            )?;
+        }
+
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/record.rs"),
+                rust_type = rust_type,
+                futhark_type = futhark_type,
+                new_fn = new_fn,
+                new_params = new_params.join(", "),
+                new_call_args = new_call_args.join(", "),
+                new_extern_params = new_extern_params.join(", "),
+            ),
+        )?;
+
+        Ok(())
+    }
+
     fn get_type(typemap: &BTreeMap<String, String>, t: &str) -> String {
         let a = typemap.get(t);
         let x = match a {
@@ -50,18 +713,39 @@ impl Rust {
 }
 
 impl Generate for Rust {
+    fn entry_ident(&self, config: &Config, name: &str) -> String {
+        rust_ident(&config.entry_name(name))
+    }
+
+    fn entry_idents(&self, config: &Config, name: &str) -> Vec<String> {
+        let ident = self.entry_ident(config, name);
+        vec![
+            async_ident(&ident),
+            format!("BatchResult::{}", camel_ident(&ident)),
+            ident,
+        ]
+    }
+
     fn array_type(
         &mut self,
-        _pkg: &Package,
+        pkg: &Package,
         config: &mut Config,
         name: &str,
         a: &manifest::ArrayType,
     ) -> Result<(), Error> {
+        self.cfg = self.type_cfgs.get(name).cloned();
         let elemtype = a.elemtype.to_str();
         let rank = a.rank;
 
         let futhark_type = convert_struct_name(&a.ctype).to_string();
-        let rust_type = format!("Array{}D{rank}", first_uppercase(elemtype));
+        let rust_type =
+            config.type_name(name, format!("Array{}D{rank}", first_uppercase(elemtype)));
+        self.wrappers.push((
+            rust_type.clone(),
+            futhark_type.clone(),
+            "ptr",
+            self.cfg.clone().unwrap_or_default(),
+        ));
         let info = ArrayInfo {
             futhark_type,
             rust_type,
@@ -77,21 +761,173 @@ impl Generate for Rust {
             new_dim_args.push(format!("dim{i}: i64"));
         }
 
-        writeln!(
-            config.output_file,
-            include_str!("templates/rust/array.rs"),
-            futhark_type = info.futhark_type,
-            rust_type = info.rust_type,
-            rank = a.rank,
-            elemtype = info.elem,
-            new_fn = a.ops.new,
-            free_fn = a.ops.free,
-            values_fn = a.ops.values,
-            shape_fn = a.ops.shape,
-            dim_params = dim_params.join(", "),
-            new_dim_args = new_dim_args.join(", ")
+        let (rust_elemtype, bits) = if elemtype == "f16" && !cfg!(feature = "half") {
+            ("u16".to_string(), "_bits")
+        } else {
+            (Self::get_type(&self.typemap, elemtype), "")
+        };
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/array.rs"),
+                name = name,
+                futhark_type = info.futhark_type,
+                rust_type = info.rust_type,
+                rank = a.rank,
+                elemtype = info.elem,
+                new_fn = a.ops.new,
+                free_fn = a.ops.free,
+                values_fn = a.ops.values,
+                shape_fn = a.ops.shape,
+                dim_params = dim_params.join(", "),
+                new_dim_args = new_dim_args.join(", "),
+                rust_elemtype = rust_elemtype,
+                bits = bits,
+            ),
         )?;
 
+        if matches!(elemtype, "f32" | "f64") {
+            self.emit(
+                config,
+                format!(
+                    include_str!("templates/rust/array_float.rs"),
+                    rust_type = info.rust_type,
+                    rust_elemtype = rust_elemtype,
+                ),
+            )?;
+        }
+
+        // The `u16` bit patterns of `f16` could clash with an actual `u16` array
+        if config.generic_arrays && bits.is_empty() {
+            self.emit(
+                config,
+                format!(
+                    include_str!("templates/rust/array_generic.rs"),
+                    rust_type = info.rust_type,
+                    rank = a.rank,
+                    rust_elemtype = rust_elemtype,
+                ),
+            )?;
+        }
+
+        // The data of arrays is in host memory when using the CPU backends. The raw functions
+        // are only listed in the manifest by compiler versions that generate them
+        let backend = pkg.manifest.backend;
+        let cpu = matches!(backend, Backend::C | Backend::Multicore | Backend::ISPC);
+        if let Some(values_raw_fn) = &a.ops.values_raw {
+            macro_rules! values_raw_template {
+                ($path:literal) => {
+                    format!(
+                        include_str!($path),
+                        rust_type = info.rust_type,
+                        futhark_type = info.futhark_type,
+                        values_raw_fn = values_raw_fn,
+                    )
+                };
+            }
+            let code = match backend {
+                _ if cpu => Some(format!(
+                    include_str!("templates/rust/array_raw.rs"),
+                    rust_type = info.rust_type,
+                    futhark_type = info.futhark_type,
+                    rust_elemtype = rust_elemtype,
+                    bits = bits,
+                    values_raw_fn = values_raw_fn,
+                )),
+                Backend::CUDA => Some(values_raw_template!("templates/rust/array_cuda.rs")),
+                Backend::OpenCL => Some(values_raw_template!("templates/rust/array_opencl.rs")),
+                _ => None,
+            };
+            if let Some(code) = code {
+                self.emit(config, code)?;
+            }
+        }
+
+        if let Some(new_raw_fn) = &a.ops.new_raw {
+            let new_raw_dims: Vec<_> = (0..rank).map(|_| "_: i64,").collect();
+            macro_rules! new_raw_template {
+                ($path:literal) => {
+                    format!(
+                        include_str!($path),
+                        rust_type = info.rust_type,
+                        futhark_type = info.futhark_type,
+                        rank = rank,
+                        dim_params = dim_params.join(", "),
+                        new_raw_fn = new_raw_fn,
+                        new_raw_dims = new_raw_dims.join("\n        "),
+                    )
+                };
+            }
+            let code = match backend {
+                Backend::CUDA => Some(new_raw_template!("templates/rust/array_cuda_new.rs")),
+                Backend::OpenCL => Some(new_raw_template!("templates/rust/array_opencl_new.rs")),
+                _ => None,
+            };
+            if let Some(code) = code {
+                self.emit(config, code)?;
+            }
+        }
+
+        if config.ndarray {
+            let ndarray_dim = if rank <= 6 {
+                format!("ndarray::Ix{rank}")
+            } else {
+                "ndarray::IxDyn".to_string()
+            };
+            self.emit(
+                config,
+                format!(
+                    include_str!("templates/rust/array_ndarray.rs"),
+                    rust_type = info.rust_type,
+                    rust_elemtype = rust_elemtype,
+                    bits = bits,
+                    rank = rank,
+                    ndarray_dim = ndarray_dim,
+                ),
+            )?;
+        }
+
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/array_futhark_data.rs"),
+                rust_type = info.rust_type,
+                rank = rank,
+                bits = bits,
+                data_type = format!("{elemtype:>4}"),
+            ),
+        )?;
+
+        if config.serde {
+            self.emit(
+                config,
+                format!(
+                    include_str!("templates/rust/array_serde.rs"),
+                    rust_type = info.rust_type,
+                    rust_elemtype = rust_elemtype,
+                    bits = bits,
+                    rank = rank,
+                ),
+            )?;
+        }
+
+        if rank == 1 {
+            self.emit(
+                config,
+                format!(
+                    include_str!("templates/rust/array_1d.rs"),
+                    rust_type = info.rust_type,
+                    rust_elemtype = rust_elemtype,
+                    bits = bits,
+                ),
+            )?;
+        }
+
+        if let Some(metadata) = &mut self.metadata {
+            metadata
+                .types
+                .insert(name.to_string(), info.rust_type.clone());
+        }
         self.typemap
             .insert(name.to_string(), info.futhark_type.clone());
         self.typemap.insert(info.futhark_type, info.rust_type);
@@ -100,96 +936,80 @@ impl Generate for Rust {
 
     fn opaque_type(
         &mut self,
-        _pkg: &Package,
+        pkg: &Package,
         config: &mut Config,
         name: &str,
         ty: &manifest::OpaqueType,
     ) -> Result<(), Error> {
+        self.cfg = self.type_cfgs.get(name).cloned();
+
+        // Without the layout the restore function can read past the end of the input
+        let (layout, restore_unsafe, restore_doc) = match stored_layout(&pkg.manifest, name) {
+            Some(layout) => {
+                let values: Vec<_> = layout
+                    .iter()
+                    .map(|(elemtype, rank)| format!("(b\"{elemtype}\", {rank})"))
+                    .collect();
+                let values = format!("Some(&[{}])", values.join(", "));
+                (values, "", RESTORE_CHECKED)
+            }
+            None => ("None".to_string(), "unsafe ", RESTORE_SAFETY),
+        };
         let futhark_type = convert_struct_name(&ty.ctype).to_string();
         let mut rust_type = first_uppercase(futhark_type.strip_prefix("futhark_opaque_").unwrap());
         if rust_type.chars().next().unwrap().is_numeric() || name.contains(' ') {
             rust_type = format!("Type{}", rust_type);
         }
+        let rust_type = config.type_name(name, rust_type);
 
-        writeln!(
-            config.output_file,
-            include_str!("templates/rust/opaque.rs"),
-            futhark_type = futhark_type,
-            rust_type = rust_type,
-            free_fn = ty.ops.free,
+        self.wrappers.push((
+            rust_type.clone(),
+            futhark_type.clone(),
+            "data",
+            self.cfg.clone().unwrap_or_default(),
+        ));
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/opaque.rs"),
+                futhark_type = futhark_type,
+                rust_type = rust_type,
+                description = opaque_description(name, ty),
+                free_fn = ty.ops.free,
+                store_fn = ty.ops.store,
+                restore_fn = ty.ops.restore,
+                stored_layout = layout,
+                restore_unsafe = restore_unsafe,
+                restore_doc = restore_doc,
+            ),
         )?;
 
-        let record = match &ty.record {
-            Some(r) => r,
-            None => {
-                self.typemap.insert(name.to_string(), futhark_type.clone());
-                self.typemap.insert(futhark_type, rust_type);
-                return Ok(());
-            }
-        };
-
-        let mut new_call_args = vec![];
-        let mut new_params = vec![];
-        let mut new_extern_params = vec![];
-        for field in record.fields.iter() {
-            // Build new function
-            let a = Self::get_type(&self.typemap, &field.r#type);
-            let t = Self::get_type(&self.typemap, &a);
-
-            let u = if t == field.r#type {
-                t.to_string()
-            } else {
-                format!("&{t}")
-            };
-
-            if type_is_opaque(&a) {
-                new_call_args.push(format!("field{}.data", field.name));
-                new_extern_params.push(format!("field{}: *const {a}", field.name));
-            } else if type_is_array(&t) {
-                new_call_args.push(format!("field{}.ptr", field.name));
-                new_extern_params.push(format!("field{}: *const {a}", field.name));
-            } else {
-                new_call_args.push(format!("field{}", field.name));
-                new_extern_params.push(format!("field{}: {a}", field.name));
-            }
-
-            new_params.push(format!("field{}: {u}", field.name));
+        if let Some(metadata) = &mut self.metadata {
+            metadata.types.insert(name.to_string(), rust_type.clone());
+        }
 
-            // Implement get function
+        if let Some(sum) = &ty.sum {
+            self.sum_type(config, &rust_type, &futhark_type, sum)?;
+        }
 
-            // If the output type is an array or opaque type then we need to wrap the return value
-            let (output, futhark_field_type) = if type_is_opaque(&a) || type_is_array(&t) {
-                (
-                    format!("Ok({t}::from_ptr(self.ctx, out))"),
-                    format!("*mut {a}"),
-                )
-            } else {
-                ("Ok(out)".to_string(), a)
-            };
+        if let Some(array) = &ty.opaque_array {
+            self.opaque_array_type(config, &rust_type, &futhark_type, array)?;
+        }
 
-            writeln!(
-                config.output_file,
-                include_str!("templates/rust/record_project.rs"),
-                project_fn = field.project,
-                rust_type = rust_type,
-                futhark_type = futhark_type,
-                field_name = field.name,
-                futhark_field_type = futhark_field_type,
-                rust_field_type = t,
-                output = output
+        if let Some(record) = &ty.record {
+            self.record_type(
+                config,
+                &rust_type,
+                &futhark_type,
+                &record.new,
+                &record.fields,
             )?;
         }
 
-        writeln!(
-            config.output_file,
-            include_str!("templates/rust/record.rs"),
-            rust_type = rust_type,
-            futhark_type = futhark_type,
-            new_fn = record.new,
-            new_params = new_params.join(", "),
-            new_call_args = new_call_args.join(", "),
-            new_extern_params = new_extern_params.join(", "),
-        )?;
+        if let Some(array) = &ty.record_array {
+            self.opaque_array_type(config, &rust_type, &futhark_type, &array.array)?;
+            self.record_type(config, &rust_type, &futhark_type, &array.zip, &array.fields)?;
+        }
 
         self.typemap.insert(name.to_string(), futhark_type.clone());
         self.typemap.insert(futhark_type, rust_type);
@@ -199,17 +1019,23 @@ impl Generate for Rust {
 
     fn entry(
         &mut self,
-        _pkg: &Package,
+        pkg: &Package,
         config: &mut Config,
         name: &str,
         entry: &manifest::Entry,
     ) -> Result<(), Error> {
         let mut call_args = Vec::new();
         let mut entry_params = Vec::new();
+        let mut entry_param_types = Vec::new();
         let mut return_type = Vec::new();
         let mut out_decl = Vec::new();
         let mut futhark_entry_params = Vec::new();
         let mut entry_return = Vec::new();
+        let mut consumed = Vec::new();
+        let mut arg_names = Vec::new();
+        let base_name = self.entry_ident(config, name);
+        let fn_name = raw_ident(&base_name);
+        self.cfg = self.entry_cfgs.get(name).cloned();
 
         // Output arguments
         for (i, arg) in entry.outputs.iter().enumerate() {
@@ -219,13 +1045,13 @@ impl Generate for Rust {
 
             let t = Self::get_type(&self.typemap, &a);
 
-            if type_is_array(&t) || type_is_opaque(&a) {
+            if type_is_array(&a) || type_is_opaque(&a) {
                 futhark_entry_params.push(format!("{name}: *mut *mut {a}"));
             } else {
                 futhark_entry_params.push(format!("{name}: *mut {a}"));
             }
 
-            if type_is_array(&t) || type_is_opaque(&a) {
+            if type_is_array(&a) || type_is_opaque(&a) {
                 entry_return.push(format!("{t}::from_ptr(self, {name}.assume_init())",));
             } else {
                 entry_return.push(format!("{name}.assume_init()"));
@@ -240,22 +1066,29 @@ impl Generate for Rust {
         for (i, arg) in entry.inputs.iter().enumerate() {
             let a = Self::get_type(&self.typemap, &arg.r#type);
             let name = format!("input{i}");
+            arg_names.push(name.clone());
 
             let t = Self::get_type(&self.typemap, &a);
 
-            if type_is_array(&t) {
-                futhark_entry_params.push(format!("{name}: *const {a}"));
-
-                entry_params.push(format!("{name}: &{t}"));
-                call_args.push(format!("{name}.ptr as *mut _"));
-            } else if type_is_opaque(&a) {
+            if type_is_array(&a) || type_is_opaque(&a) {
                 futhark_entry_params.push(format!("{name}: *const {a}"));
 
-                entry_params.push(format!("{name}: &{t}"));
-                call_args.push(format!("{name}.data as *mut _"));
+                // Unique parameters are consumed by Futhark, they are moved into the function
+                // and only freed afterwards
+                let p = if arg.unique {
+                    consumed.push(format!("`{name}`"));
+                    t.clone()
+                } else {
+                    format!("&{t}")
+                };
+                entry_params.push(format!("{name}: {p}"));
+                entry_param_types.push(p);
+                let field = if type_is_array(&a) { "ptr" } else { "data" };
+                call_args.push(format!("{name}.{field} as *mut _"));
             } else {
                 futhark_entry_params.push(format!("{name}: {a}"));
                 entry_params.push(format!("{name}: {t}"));
+                entry_param_types.push(t);
                 call_args.push(name);
             }
         }
@@ -268,74 +1101,687 @@ impl Generate for Rust {
                 format!("({})", entry_return.join(", ")),
             ),
         };
+        let batch_variant = camel_ident(&base_name);
 
-        writeln!(
-            config.output_file,
-            include_str!("templates/rust/entry.rs"),
-            entry_fn = entry.cfun,
-            entry_name = name,
-            entry_params = entry_params.join(", "),
-            entry_return_type = entry_return_type,
-            out_decl = out_decl.join(";\n"),
-            call_args = call_args.join(", "),
-            entry_return = entry_return,
-            futhark_entry_params = futhark_entry_params.join(", "),
+        // Unit results are written out as `()` to keep clippy's unit lints quiet
+        let (entry_results, sync_return, batch_push) = if entry.outputs.is_empty() {
+            (
+                "let results = ();".to_string(),
+                "let () = pending.results;\n        Ok(())".to_string(),
+                format!(
+                    "let () = pending.results;\n                \
+                    self.results.push(BatchResult::{batch_variant}(()));"
+                ),
+            )
+        } else {
+            (
+                format!(
+                    "#[allow(unused_unsafe)]\n        let results = unsafe {{ {entry_return} }};"
+                ),
+                "Ok(pending.results)".to_string(),
+                format!("self.results.push(BatchResult::{batch_variant}(pending.results));"),
+            )
+        };
+        let cfg = self
+            .cfg
+            .as_ref()
+            .map(|c| format!("{c}\n    "))
+            .unwrap_or_default();
+        self.batch_variants
+            .push(format!("{cfg}{batch_variant}({entry_return_type}),"));
+
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/entry.rs"),
+                entry_fn = entry.cfun,
+                entry_name = name,
+                fn_name = fn_name,
+                async_fn = async_ident(&base_name),
+                entry_signature = entry_signature(name, entry),
+                entry_doc = if consumed.is_empty() {
+                    String::new()
+                } else {
+                    format!(
+                    "\n    ///\n    /// Consumes {}, Futhark may reuse their memory for the result",
+                    consumed.join(", ")
+                )
+                },
+                entry_params = entry_params.join(", "),
+                entry_return_type = entry_return_type,
+                out_decl = out_decl.join(";\n"),
+                call_args = call_args.join(", "),
+                arg_names = arg_names.join(", "),
+                entry_results = entry_results,
+                sync_return = sync_return,
+                batch_push = batch_push,
+                futhark_entry_params = futhark_entry_params.join(", "),
+            ),
         )?;
 
+        if config.entry_trait.is_some() && !self.trait_method(pkg, name, &fn_name, entry) {
+            crate::warn(&format!(
+                "Leaving entry point {name} out of the entry point trait, it uses opaque types"
+            ));
+        }
+
+        if let Some(metadata) = &mut self.metadata {
+            metadata.entry_points.insert(
+                name.to_string(),
+                EntryMetadata {
+                    function: fn_name,
+                    params: entry_param_types,
+                    returns: entry_return_type,
+                },
+            );
+        }
+
         Ok(())
     }
 
     fn bindings(&mut self, pkg: &Package, config: &mut Config) -> Result<(), Error> {
+        self.symbol_prefix = pkg.symbol_prefix.clone();
+        self.format = config.format;
+        self.metadata = config.metadata.then(Metadata::default);
+        (self.entry_cfgs, self.type_cfgs) = config.feature_cfgs(&pkg.manifest);
         writeln!(config.output_file, "// Generated by futhark-bindgen\n")?;
-        let backend_extern_functions = match &pkg.manifest.backend {
-            Backend::Multicore => {
-                "fn futhark_context_config_set_num_threads(_: *mut futhark_context_config, _: std::os::raw::c_int);"
-            }
-            Backend::OpenCL | Backend::CUDA => {
-                "fn futhark_context_config_set_device(_: *mut futhark_context_config, _: *const std::os::raw::c_char);"
-            }
-            _ => "",
+        if let Some(name) = &config.module {
+            let allow = if config.allow_lints { ALLOW_LINTS } else { "" };
+            let vis = config.visibility.to_str();
+            writeln!(config.output_file, "{allow}\n{vis}mod {name} {{\n")?;
+        }
+
+        let source = std::fs::read(&pkg.src).ok();
+        let compiler_version = match &pkg.compiler_version {
+            Some(version) => version.clone(),
+            None => format!("Futhark {}", pkg.manifest.version),
         };
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/provenance.rs"),
+                backend = pkg.manifest.backend.to_str(),
+                compiler_version = compiler_version,
+                source_sha256 = source.as_deref().map(sha256_hex).unwrap_or_default(),
+            ),
+        )?;
+        if config.embedded_source {
+            let source = std::fs::read_to_string(&pkg.src)?;
+            self.emit(
+                config,
+                format!("/// Futhark source file of the program\npub const FUTHARK_SOURCE: &str = {source:?};\n"),
+            )?;
+        }
 
-        let backend_options = match pkg.manifest.backend {
-            Backend::Multicore => {
-                "pub fn threads(mut self, n: u32) -> Options { self.num_threads = n as u32; self }"
-            }
-            Backend::CUDA | Backend::OpenCL => {
-                "pub fn device(mut self, s: impl AsRef<str>) -> Options { self.device = Some(std::ffi::CString::new(s.as_ref()).expect(\"Invalid device\")); self }"
-            }
-            _ => "",
+        let backend = pkg.manifest.backend;
+        let mut options = Vec::new();
+        if matches!(backend, Backend::Multicore | Backend::ISPC) {
+            options.push(BACKEND_OPTION_THREADS);
+        }
+        if matches!(backend, Backend::CUDA | Backend::OpenCL | Backend::HIP) {
+            options.push(BACKEND_OPTION_DEVICE);
+        }
+        if matches!(backend, Backend::CUDA | Backend::OpenCL) {
+            options.push(BACKEND_OPTION_GROUP_SIZE);
+            options.push(BACKEND_OPTION_NUM_GROUPS);
+        }
+        if backend == Backend::OpenCL {
+            options.push(BACKEND_OPTION_PLATFORM);
+        }
+        if backend == Backend::CUDA {
+            options.extend([
+                BACKEND_OPTION_CUDA_DEVICE,
+                BACKEND_OPTION_NVRTC,
+                BACKEND_OPTION_DUMP_PTX,
+                BACKEND_OPTION_LOAD_PTX,
+            ]);
+        }
+        let join = |f: fn(&BackendOption) -> &str, sep| {
+            options
+                .iter()
+                .map(f)
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join(sep)
         };
 
-        let configure_num_threads = if pkg.manifest.backend == Backend::Multicore {
-            "futhark_context_config_set_num_threads(config, options.num_threads as std::os::raw::c_int);"
+        let tuning_file = match &pkg.tuning_file {
+            Some(path) => Some((path, std::fs::read_to_string(path)?)),
+            None => None,
+        };
+        let tuning_params = match &tuning_file {
+            Some((path, src)) => parse_tuning_file(path, src)?,
+            None => Vec::new(),
+        };
+        let configure_tuning_params = if tuning_params.is_empty() {
+            ""
         } else {
-            "let _ = &options.num_threads;"
+            // The names come from the tuning file, a parameter Futhark doesn't know is reported
+            // the same way as one set with `Options::set_tuning_param`
+            "for (name, value) in TUNING_PARAMS {
+                let name_ptr = name.as_ptr() as *const std::os::raw::c_char;
+                if futhark_context_config_set_tuning_param(config, name_ptr, *value) != 0 {
+                    futhark_context_config_free(config);
+                    return Err(Error::UnknownTuningParam(name.trim_end_matches('\\0').to_string()));
+                }
+            }"
         };
 
-        let configure_set_device = if matches!(
-            pkg.manifest.backend,
-            Backend::CUDA | Backend::OpenCL
-        ) {
-            "if let Some(d) = &options.device { futhark_context_config_set_device(config, d.as_ptr()); }"
-        } else {
-            "let _ = &options.device;"
+        let tuning_param_names = pkg
+            .manifest
+            .tuning_params
+            .keys()
+            .map(|name| format!("{name:?}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/context.rs"),
+                backend_fields = join(|o| o.field, "\n    "),
+                backend_defaults = join(|o| o.default, "\n            "),
+                backend_options = join(|o| o.method, "\n\n    "),
+                configure_backend = join(|o| o.configure, "\n            "),
+                configure_tuning_params = configure_tuning_params,
+                program_hash = program_hash(pkg),
+                backend = pkg.manifest.backend.to_str(),
+                tuning_param_names = tuning_param_names,
+                warn_unknown_tuning_param = WARN_UNKNOWN_TUNING_PARAM,
+                backend_extern_functions = join(|o| o.extern_fn, "\n    "),
+            ),
+        )?;
+
+        if pkg.manifest.backend == Backend::OpenCL {
+            self.emit(
+                config,
+                include_str!("templates/rust/context_opencl.rs").to_string(),
+            )?;
+        }
+
+        let mut futhark_data = include_str!("templates/rust/futhark_data.rs").to_string();
+        #[cfg(feature = "half")]
+        futhark_data.push_str("\n    element!(half::f16, b\" f16\");\n");
+        futhark_data.push_str("}\n");
+        self.emit(config, futhark_data)?;
+
+        if config.serde {
+            self.emit(
+                config,
+                include_str!("templates/rust/array_data.rs").to_string(),
+            )?;
+        }
+
+        if config.generic_arrays {
+            self.emit(
+                config,
+                include_str!("templates/rust/array_generic_traits.rs").to_string(),
+            )?;
+        }
+
+        let manifest = &pkg.manifest;
+        let entry_types = manifest.entry_points.values().flat_map(|entry| {
+            let inputs = entry.inputs.iter().map(|x| x.r#type.as_str());
+            inputs.chain(entry.outputs.iter().map(|x| x.r#type.as_str()))
+        });
+        let uses_f16 = entry_types
+            .chain(manifest.types.keys().map(|x| x.as_str()))
+            .any(|t| uses_f16_scalar(manifest, t));
+        if uses_f16 && !cfg!(feature = "half") {
+            self.emit(
+                config,
+                include_str!("templates/rust/f16_bits.rs").to_string(),
+            )?;
+            self.sys.push_str("use super::F16Bits;\n");
+        }
+
+        #[cfg(feature = "log")]
+        let logger = {
+            let program = pkg.src.file_stem().unwrap_or_default().to_string_lossy();
+            format!(include_str!("templates/rust/logger.rs"), program = program)
         };
+        #[cfg(not(feature = "log"))]
+        let logger = include_str!("templates/rust/logger_stderr.rs").to_string();
+        self.emit(config, logger)?;
+
+        if !tuning_params.is_empty() {
+            let params: Vec<_> = tuning_params
+                .iter()
+                .map(|(name, value)| format!("(\"{name}\\0\", {value})"))
+                .collect();
+            writeln!(
+                config.output_file,
+                "/// Tuning parameters found by `futhark autotune`\nconst TUNING_PARAMS: &[(&str, usize)] = &[{}];\n",
+                params.join(", ")
+            )?;
+        }
+
+        if !pkg.manifest.tuning_params.is_empty() {
+            let mut code =
+                "/// Names of the tuning parameters, see `Options::tuning_param`\npub mod tuning_param {\n"
+                    .to_string();
+            for (name, param) in &pkg.manifest.tuning_params {
+                let mut ident = name
+                    .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+                    .to_ascii_uppercase();
+                if ident.starts_with(|c: char| c.is_ascii_digit()) {
+                    ident.insert(0, '_');
+                }
+                code += &format!(
+                    "    /// Tuning parameter of class `{}`\n    pub const {ident}: &str = {name:?};\n",
+                    param.class
+                );
+            }
+            code.push_str("}\n");
+            self.emit(config, code)?;
+        }
 
+        Ok(())
+    }
+
+    fn finish(&mut self, _pkg: &Package, config: &mut Config) -> Result<(), Error> {
+        self.cfg = None;
+        let variants = std::mem::take(&mut self.batch_variants);
+        self.emit(
+            config,
+            format!(
+                include_str!("templates/rust/batch.rs"),
+                variants = variants.join("\n    ")
+            ),
+        )?;
+
+        if let Some(name) = &config.entry_trait {
+            let (signatures, bodies): (Vec<_>, Vec<_>) =
+                std::mem::take(&mut self.trait_methods).into_iter().unzip();
+            let code = format!(
+                "/// Entry points of the program using values in host memory, implemented by `Context`\n\
+                pub trait {name} {{\n    {}\n}}\n\n\
+                impl {name} for Context {{\n    {}\n}}\n",
+                signatures.join(";\n\n    ") + if signatures.is_empty() { "" } else { ";" },
+                bodies.join("\n\n    "),
+            );
+            self.emit(config, code)?;
+        }
+
+        if !self.wrappers.is_empty() {
+            writeln!(
+                config.output_file,
+                "// Futhark values are only used through their thread safe context, and they never \
+                change after\n// they are created, entry points that consume a value take \
+                ownership of it"
+            )?;
+        }
+        for (rust_type, _, _, cfg) in &self.wrappers {
+            let cfg = if cfg.is_empty() {
+                String::new()
+            } else {
+                format!("{cfg}\n")
+            };
+            writeln!(
+                config.output_file,
+                "{cfg}unsafe impl Send for {rust_type} {{}}\n{cfg}unsafe impl Sync for {rust_type} {{}}\n"
+            )?;
+        }
+
+        let vis = if config.raw_bindings {
+            let mut code =
+                "impl Context {\n    /// Pointer to the Futhark context, for use with `sys`\n    \
+                pub fn as_raw(&self) -> *mut sys::futhark_context { self.inner.context }\n}\n"
+                    .to_string();
+            for (rust_type, futhark_type, field, cfg) in &self.wrappers {
+                let cfg = if cfg.is_empty() {
+                    String::new()
+                } else {
+                    format!("{cfg}\n")
+                };
+                code += &format!(
+                    "\n{cfg}impl {rust_type} {{\n    /// Pointer to the Futhark value, for use with \
+                    `sys`. It is still owned and freed by `self`\n    \
+                    pub fn as_raw(&self) -> *mut sys::{futhark_type} {{ self.{field} }}\n}}\n"
+                );
+            }
+            self.emit(config, code)?;
+            writeln!(config.output_file, "/// Declarations of the C API")?;
+            if config.module.is_some() {
+                "pub "
+            } else {
+                config.visibility.to_str()
+            }
+        } else {
+            ""
+        };
+        let allow_docs = if config.allow_lints {
+            ", missing_docs"
+        } else {
+            ""
+        };
         writeln!(
             config.output_file,
-            include_str!("templates/rust/context.rs"),
-            backend_options = backend_options,
-            configure_num_threads = configure_num_threads,
-            configure_set_device = configure_set_device,
-            backend_extern_functions = backend_extern_functions,
+            "#[allow(unused, non_camel_case_types{allow_docs})]\n{vis}mod sys {{\n{}}}\n\nuse sys::*;",
+            std::mem::take(&mut self.sys)
         )?;
-
+        if config.module.is_some() {
+            writeln!(config.output_file, "}}")?;
+        }
         Ok(())
     }
 
-    fn format(&mut self, path: &std::path::Path) -> Result<(), Error> {
-        let _ = std::process::Command::new("rustfmt").arg(path).status();
-        Ok(())
+    fn format(&mut self, path: &std::path::Path, mut code: String) -> Result<String, Error> {
+        if let Some(prefix) = &self.symbol_prefix {
+            code = add_link_names(&code, prefix);
+        }
+        if self.format {
+            // Parse errors point at the input, anything else means rustfmt itself isn't usable
+            let child = std::process::Command::new("rustfmt")
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .spawn();
+            match child {
+                Ok(mut child) => {
+                    if let Some(mut stdin) = child.stdin.take() {
+                        // rustfmt reads all of its input before writing anything, if it exits
+                        // early the error is reported below
+                        let _ = stdin.write_all(code.as_bytes());
+                    }
+                    let output = child.wait_with_output()?;
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    if output.status.success() {
+                        code = String::from_utf8_lossy(&output.stdout).into_owned();
+                    } else if stderr.contains("<stdin>") {
+                        return Err(Error::InvalidGeneratedCode {
+                            path: path.to_path_buf(),
+                            message: stderr.into_owned(),
+                        });
+                    } else {
+                        crate::warn(&format!(
+                            "rustfmt failed, {} is left unformatted: {}",
+                            path.display(),
+                            stderr.trim_end()
+                        ));
+                    }
+                }
+                Err(e) => crate::warn(&format!(
+                    "unable to run rustfmt, {} is left unformatted: {e}",
+                    path.display()
+                )),
+            }
+        }
+
+        if let Some(metadata) = &self.metadata {
+            let mut meta_path = path.as_os_str().to_os_string();
+            meta_path.push(".meta.json");
+            let json = serde_json::to_string_pretty(metadata)?;
+            write_if_changed(std::path::Path::new(&meta_path), json.as_bytes())?;
+        }
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuning_file() {
+        let path = std::path::Path::new("prog.tuning");
+        let params = parse_tuning_file(path, "a=1\n\n  b = 2  \n").unwrap();
+        assert_eq!(params, [("a", 1), ("b", 2)]);
+
+        for src in ["a=1\nb\n", "a=x\n"] {
+            let err = parse_tuning_file(path, src).unwrap_err();
+            assert!(matches!(err, Error::InvalidTuningFile { .. }));
+        }
+        let err = parse_tuning_file(path, "a=1\nb\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid tuning file prog.tuning: expected name=value, found `b`"
+        );
+    }
+
+    fn rustfmt_available() -> bool {
+        std::process::Command::new("rustfmt")
+            .arg("--version")
+            .output()
+            .is_ok_and(|out| out.status.success())
+    }
+
+    #[test]
+    fn format_rejects_invalid_code() {
+        if !rustfmt_available() {
+            return;
+        }
+        let path = std::path::Path::new("out.rs");
+        let err = Rust::default()
+            .format(path, "fn (".to_string())
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidGeneratedCode { .. }));
+    }
+
+    /// Generate bindings for a small manifest, formatting them fails if they don't parse
+    #[test]
+    fn generate_sample() {
+        if !rustfmt_available() {
+            return;
+        }
+        use crate::manifest::{ElemType, Entry};
+        let manifest = Manifest::new(Backend::C)
+            .with_array_type(ElemType::F32, 1)
+            .with_entry_point(
+                "sum",
+                Entry::new("sum")
+                    .with_input("xs", "[]f32", false)
+                    .with_output("f32", false),
+            )
+            .with_entry_point("nothing", Entry::new("nothing"));
+
+        let dir = std::env::temp_dir().join(format!("futhark-bindgen-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest_path = dir.join("sample.json");
+        std::fs::write(&manifest_path, serde_json::to_string(&manifest).unwrap()).unwrap();
+        let pkg = Package::from_manifest(&manifest_path).unwrap();
+
+        let out = dir.join("sample.rs");
+        let mut config = Config::new(&out).unwrap();
+        Rust::default().generate(&pkg, &mut config).unwrap();
+        let code = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(code.starts_with("// Generated by futhark-bindgen"));
+        assert!(code.contains("pub fn sum(&self, input0: &ArrayF32D1) -> Result<f32, Error>"));
+        assert!(code.contains("pub fn nothing(&self) -> Result<(), Error>"));
+    }
+
+    /// The `.meta.json` format is read by other tools, changing it is a breaking change
+    #[test]
+    fn metadata_format() {
+        use crate::manifest::{ElemType, Entry};
+        let manifest = Manifest::new(Backend::C)
+            .with_array_type(ElemType::F32, 1)
+            .with_entry_point(
+                "sum",
+                Entry::new("sum")
+                    .with_input("xs", "[]f32", false)
+                    .with_output("f32", false),
+            )
+            .with_entry_point(
+                "split",
+                Entry::new("split")
+                    .with_input("xs", "[]f32", true)
+                    .with_input("n", "i64", false)
+                    .with_output("[]f32", false)
+                    .with_output("[]f32", false),
+            )
+            .with_entry_point("nothing", Entry::new("nothing"));
+
+        let dir = std::env::temp_dir().join(format!(
+            "futhark-bindgen-metadata-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest_path = dir.join("sample.json");
+        std::fs::write(&manifest_path, serde_json::to_string(&manifest).unwrap()).unwrap();
+        let pkg = Package::from_manifest(&manifest_path).unwrap();
+
+        let out = dir.join("sample.rs");
+        let mut config = Config::new(&out)
+            .unwrap()
+            .with_format(false)
+            .with_metadata(true);
+        Rust::default().generate(&pkg, &mut config).unwrap();
+        let meta = std::fs::read_to_string(dir.join("sample.rs.meta.json")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            meta,
+            r#"{
+  "entry_points": {
+    "nothing": {
+      "function": "nothing",
+      "params": [],
+      "returns": "()"
+    },
+    "split": {
+      "function": "split",
+      "params": [
+        "ArrayF32D1",
+        "i64"
+      ],
+      "returns": "(ArrayF32D1, ArrayF32D1)"
+    },
+    "sum": {
+      "function": "sum",
+      "params": [
+        "&ArrayF32D1"
+      ],
+      "returns": "f32"
+    }
+  },
+  "types": {
+    "[]f32": "ArrayF32D1"
+  }
+}"#
+        );
+    }
+
+    /// Generated bindings build as a library for every backend and the options that change
+    /// their structure, the manifest has records, a sum type and arrays of records and the
+    /// first config embeds a tuning file
+    #[test]
+    fn generated_code_compiles() {
+        // With these features the generated code uses crates that this one doesn't depend on
+        if cfg!(any(feature = "half", feature = "log")) {
+            return;
+        }
+        let rustc = std::env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+        if std::process::Command::new(&rustc)
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!(
+            "futhark-bindgen-compile-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sample: serde_json::Value =
+            serde_json::from_str(include_str!("testdata/sample.json")).unwrap();
+
+        type Configure = fn(Config) -> Config;
+        let configs: [(Backend, Configure); 8] = [
+            (Backend::C, |c| c),
+            (Backend::C, |c| {
+                c.with_format(false).with_entry_trait("Entries")
+            }),
+            (Backend::Multicore, |c| {
+                c.with_generic_arrays(true)
+                    .with_rename_style(RenameStyle::SnakeCase)
+            }),
+            (Backend::ISPC, |c| c.feature_gate("extra", &["many", "mk"])),
+            (Backend::CUDA, |c| c.with_raw_bindings(true)),
+            (Backend::CUDA, |c| {
+                c.with_entry_trait("Entries").with_metadata(true)
+            }),
+            (Backend::OpenCL, |c| {
+                c.with_visibility(Visibility::PubCrate)
+                    .with_module(Some("futhark"))
+                    .with_allow_lints(true)
+            }),
+            (Backend::HIP, |c| {
+                c.rename_type("res", "Outcome").rename_entry("sum", "total")
+            }),
+        ];
+        for (i, (backend, f)) in configs.into_iter().enumerate() {
+            let mut manifest = sample.clone();
+            manifest["backend"] = backend.to_str().into();
+            let manifest_path = dir.join(format!("sample{i}.json"));
+            std::fs::write(&manifest_path, manifest.to_string()).unwrap();
+            // Embedded by the generated code like the output of `futhark autotune`
+            if i == 0 {
+                std::fs::write(manifest_path.with_extension("tuning"), "sum.group=64\n").unwrap();
+            }
+            let pkg = Package::from_manifest(&manifest_path).unwrap();
+
+            let out = dir.join(format!("sample{i}.rs"));
+            let mut config = f(Config::new(&out).unwrap());
+            Rust::default().generate(&pkg, &mut config).unwrap();
+
+            let res = std::process::Command::new(&rustc)
+                .args([
+                    "--edition",
+                    "2021",
+                    "--crate-type",
+                    "lib",
+                    "--emit",
+                    "metadata",
+                ])
+                .arg("--crate-name")
+                .arg(format!("sample{i}"))
+                .arg("--out-dir")
+                .arg(&dir)
+                .arg(&out)
+                .output()
+                .unwrap();
+            assert!(
+                res.status.success(),
+                "bindings for {} with config {i} don't compile:\n{}",
+                backend.to_str(),
+                String::from_utf8_lossy(&res.stderr)
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Methods using the raw array functions are only generated for arrays whose ops list them,
+    /// in the sample `[]f32` has them and `[]i32` doesn't
+    #[test]
+    fn raw_array_methods_follow_manifest() {
+        let dir =
+            std::env::temp_dir().join(format!("futhark-bindgen-raw-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sample: serde_json::Value =
+            serde_json::from_str(include_str!("testdata/sample.json")).unwrap();
+        for (backend, methods) in [
+            (Backend::C, &["fn as_ptr(", "fn as_slice("][..]),
+            (Backend::CUDA, &["fn device_ptr(", "unsafe fn from_raw("]),
+            (Backend::OpenCL, &["fn cl_mem(", "unsafe fn from_cl_mem("]),
+        ] {
+            let mut manifest = sample.clone();
+            manifest["backend"] = backend.to_str().into();
+            let manifest_path = dir.join("sample.json");
+            std::fs::write(&manifest_path, manifest.to_string()).unwrap();
+            let pkg = Package::from_manifest(&manifest_path).unwrap();
+
+            let out = dir.join("sample.rs");
+            let mut config = Config::new(&out).unwrap().with_format(false);
+            Rust::default().generate(&pkg, &mut config).unwrap();
+            let code = std::fs::read_to_string(&out).unwrap();
+            for method in methods {
+                assert_eq!(code.matches(method).count(), 1, "{method}");
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
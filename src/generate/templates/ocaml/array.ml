@@ -17,6 +17,7 @@ module {module_name} = struct
   let v ctx ba =
     check_use_after_free `context ctx.Context.context_free;
     let dims = Genarray.dims ba in
+    if Array.length dims <> {rank} then raise (Error (InvalidShape ({rank}, Array.length dims)));
     let ptr = Bindings.futhark_new_{elemtype}_{rank}d ctx.Context.handle (cast @@ bigarray_start genarray ba) {dim_args} in
     if is_null ptr then raise (Error NullPtr);
     Context.auto_sync ctx;
@@ -29,8 +30,9 @@ module {module_name} = struct
     let b = Array.fold_left ( * ) 1 dims in
     if (a <> b) then raise (Error (InvalidShape (a, b)));
     let rc = Bindings.futhark_values_{elemtype}_{rank}d t.ctx.Context.handle (get_ptr t) (cast @@ bigarray_start genarray ba) in
-    Context.auto_sync t.ctx;
-    if rc <> 0 then raise (Error (Code rc))
+    if rc <> 0 then raise (Error (Code rc));
+    (* [ba] is only written once the copy from the device has finished *)
+    Context.sync t.ctx
 
   let values_array1 t ba =
     let ba = genarray_of_array1 ba in
@@ -49,11 +51,12 @@ module {module_name} = struct
     values_array1 t g;
     g
 
-  let shape t = t.shape
+  let shape t = Array.copy t.shape
 
   let of_array1 ctx dims arr =
+    if Array.length dims <> {rank} then raise (Error (InvalidShape ({rank}, Array.length dims)));
     let len = Array.fold_left ( * ) 1 dims in
-    assert (len = Array1.dim arr);
+    if len <> Array1.dim arr then raise (Error (InvalidShape (len, Array1.dim arr)));
     let g = genarray_of_array1 arr in
     let g = reshape g dims in
     v ctx g
@@ -73,7 +76,7 @@ module {module_name} = struct
     {{ ptr = Ctypes.allocate ~finalise:(free ctx) (Ctypes.ptr Ctypes.void) ptr; ctx; shape }}
 
   let free t = free t.ctx t.ptr
-    
+{extra}
   let _ = of_ptr
 end
 
@@ -1,6 +1,6 @@
 module {module_name}: sig
   type t
-  (** Futhark array *)
+  (** Futhark array [{name}] *)
 
   type kind = ({ocaml_elemtype}, {ba_elemtype}) Bigarray.kind
   (** The Bigarray kind that matches the correct element type for this array *)
@@ -26,11 +26,12 @@ module {module_name}: sig
   (** Similar to [get] but returns an [Array1] *)
 
   val of_array: Context.t -> int array -> ({ocaml_elemtype}) array -> t
-  (** Create [t] from an array of values *)
+  (** Create [t] from an array of values, raises [Error (InvalidShape _)] when the number of
+      values or dimensions doesn't match *)
 
   val of_array1: Context.t -> int array ->  ({ocaml_elemtype}, {ba_elemtype}, Bigarray.c_layout) Bigarray.Array1.t-> t
-  (** Create [t] from an [Array1] instead of [Genarray] *)
+  (** Create [t] from an [Array1] instead of [Genarray], with the same checks as [of_array] *)
 
   val free: t -> unit
-  (** Free the array *)
+  (** Free the array *){extra}
 end
@@ -0,0 +1,7 @@
+
+  let of_bool_array ctx dims arr =
+    of_array ctx dims (Array.map (fun b -> if b then 1 else 0) arr)
+
+  let get_bool_array t =
+    let a = get_array1 t in
+    Array.init (Array1.dim a) (fun i -> a.{i} <> 0)
@@ -0,0 +1,6 @@
+
+  val of_bool_array: Context.t -> int array -> bool array -> t
+  (** Create [t] from an array of booleans, they are stored as 0 or 1 *)
+
+  val get_bool_array: t -> bool array
+  (** Get the values as a flat array of booleans *)
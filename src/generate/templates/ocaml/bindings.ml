@@ -2,6 +2,40 @@ open Ctypes
 open! Unsigned
 open! Signed
 
+(* Futhark f16 values are passed as their bit pattern and converted to and from float *)
+let float_of_f16 h =
+  let h = UInt16.to_int h in
+  let exp = (h lsr 10) land 0x1f and man = h land 0x3ff in
+  let x =
+    if exp = 0 then ldexp (Float.of_int man) (-24)
+    else if exp = 0x1f then (if man = 0 then infinity else nan)
+    else ldexp (Float.of_int (man lor 0x400)) (exp - 25)
+  in
+  if h land 0x8000 <> 0 then -. x else x
+
+(* Rounds to the nearest f16 value with ties to even *)
+let f16_of_float x =
+  let b = Int64.bits_of_float x in
+  let sign = if Int64.compare b 0L < 0 then 0x8000 else 0 in
+  let exp = Int64.to_int (Int64.shift_right_logical b 52) land 0x7ff in
+  let man = Int64.to_int (Int64.logand b 0xfffffffffffffL) in
+  let h =
+    if exp = 0x7ff then (if man = 0 then 0x7c00 else 0x7e00)
+    else
+      let e = exp - 1008 in
+      if e >= 0x1f then 0x7c00
+      else
+        let m = man lor (1 lsl 52) in
+        let shift = min 60 (if e > 0 then 42 else 43 - e) in
+        let r = m lsr shift and rest = m land ((1 lsl shift) - 1) and half = 1 lsl (shift - 1) in
+        let r = if rest > half || (rest = half && r land 1 = 1) then r + 1 else r in
+        (if e > 0 then (e - 1) lsl 10 else 0) + r
+  in
+  UInt16.of_int (sign lor h)
+
+let f16 = view ~read:float_of_f16 ~write:f16_of_float uint16_t
+let _ = f16
+
 module Bindings = struct
   external _stub: unit -> unit = "futhark_context_new"
 
@@ -33,6 +67,7 @@ type error =
   | NullPtr
   | Code of int
   | UseAfterFree of [`context | `array | `opaque]
+  | InvalidData
 
 exception Error of error
 
@@ -49,6 +84,7 @@ let () = Printexc.register_printer (function
   | Error (UseAfterFree `context) -> Some "futhark: context used after beeing freed"
   | Error (UseAfterFree `array) -> Some "futhark: array used after beeing freed"
   | Error (UseAfterFree `opaque) -> Some "futhark: opaque value used after beeing freed"
+  | Error InvalidData -> Some "futhark error: invalid stored value"
   | _ -> None)
 
 
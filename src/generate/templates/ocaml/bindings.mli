@@ -6,5 +6,6 @@ type error =
   | NullPtr 
   | Code of int
   | UseAfterFree of [`context | `array | `opaque]
+  | InvalidData
 
 exception Error of error
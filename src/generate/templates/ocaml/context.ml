@@ -84,5 +84,42 @@ let get_opaque_ptr t =
   let x = !@(t.opaque_ptr) in
   check_use_after_free `opaque (Ctypes.is_null x);
   x
+
+(* Check that [b] consists of one or more complete values in the binary data format, this is
+   how opaque values are stored and the restore functions don't know the length of their input.
+   With [Some layout] the values must also have exactly those types and ranks *)
+let stored_is_valid layout b =
+  let len = Bytes.length b in
+  let rec go i layout =
+    if i = len then (match layout with Some (_ :: _) -> false | _ -> true)
+    else if len - i < 7 || Bytes.get b i <> 'b' || Bytes.get_uint8 b (i + 1) <> 2 then false
+    else
+      let rank = Bytes.get_uint8 b (i + 2) in
+      let ty = Bytes.sub_string b (i + 3) 4 in
+      let size = match ty with
+        | "  i8" | "  u8" | "bool" -> 1
+        | " i16" | " u16" | " f16" -> 2
+        | " i32" | " u32" | " f32" -> 4
+        | " i64" | " u64" | " f64" -> 8
+        | _ -> 0 in
+      let matches, rest = match layout with
+        | None -> true, None
+        | Some ((t, r) :: rest) -> t = ty && r = rank, Some rest
+        | Some [] -> false, None in
+      let data = i + 7 + 8 * rank in
+      if not matches || size = 0 || data > len then false
+      else
+        let rec count j n =
+          if j = rank then Some n
+          else
+            let d = Bytes.get_int64_le b (i + 7 + 8 * j) in
+            if d < 0L || (d > 0L && n > Int64.div Int64.max_int d) then None
+            else count (j + 1) (Int64.mul n d) in
+        match count 0 1L with
+        | Some n when n <= Int64.of_int ((len - data) / size) ->
+          go (data + Int64.to_int n * size) rest
+        | _ -> false
+  in
+  len > 0 && go 0 layout
 [@@@ocaml.warning "+32"]
 
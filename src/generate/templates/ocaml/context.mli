@@ -1,6 +1,6 @@
 module Context: sig
   type t
-  (** Futhark context *)
+  (** Futhark context, the library was compiled with the [{backend}] backend *)
 
   val v: ?debug:bool -> ?log:bool -> ?profile:bool -> ?cache_file:string -> ?auto_sync:bool -> {extra_mli} unit -> t
   (** Create a new context *)
@@ -1,2 +1,4 @@
-(** Entry point: {name} *)
-val {name}: Context.t -> {arg_types} -> ({return_type})
+(** Entry point: {name}
+
+    Futhark signature: [{signature}] *)
+val {fn_name}: {arg_types} -> ({return_type})
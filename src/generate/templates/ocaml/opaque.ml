@@ -14,4 +14,27 @@
 
   let free t = free' t.opaque_ctx t.opaque_ptr 
 
+  let store t =
+    check_use_after_free `context t.opaque_ctx.Context.context_free;
+    let p = allocate (ptr void) null in
+    let n = allocate size_t Size_t.zero in
+    let rc = Bindings.{store_fn} t.opaque_ctx.Context.handle (get_opaque_ptr t) p n in
+    if rc <> 0 then raise (Error (Code rc));
+    (* The buffer is only written once the copy from the device has finished *)
+    Context.sync t.opaque_ctx;
+    let b = Bytes.of_string (string_from_ptr (from_voidp char !@p) ~length:(Size_t.to_int !@n)) in
+    Bindings.free !@p;
+    b
+
+  let restore ctx b =
+    check_use_after_free `context ctx.Context.context_free;
+    if not (stored_is_valid {stored_layout} b) then raise (Error InvalidData);
+    let buf = CArray.of_string (Bytes.to_string b) in
+    let ptr = Bindings.{restore_fn} ctx.Context.handle (to_voidp (CArray.start buf)) in
+    let t = of_ptr ctx ptr in
+    (* [buf] can be collected once this returns, the copy to the device must have finished *)
+    Context.sync ctx;
+    ignore (Sys.opaque_identity buf);
+    t
+
   let _ = of_ptr
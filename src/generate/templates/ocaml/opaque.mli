@@ -1,5 +1,11 @@
   type t
-  (** Futhark type *)
+  (** Futhark type [{description}] *)
   
   val free: t -> unit
   (** Free function *)
+
+  val store: t -> bytes
+  (** Serialize the value, the result can be passed to [restore] *)
+
+  val restore: Context.t -> bytes -> t
+  (** Restore a value from the result of [store], {restore_doc} *)
@@ -3,6 +3,6 @@
     let out = allocate_n ~count:1 {s} in
     let rc = Bindings.{project} t.opaque_ctx.Context.handle out (get_opaque_ptr t) in
     if rc <> 0 then raise (Error (Code rc));
-    Context.auto_sync t.opaque_ctx;
+    Context.{sync} t.opaque_ctx;
     {out}
 
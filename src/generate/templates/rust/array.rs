@@ -1,110 +1,167 @@
-#[repr(C)]
-#[allow(non_camel_case_types)]
-struct {futhark_type} {{
-    _private: [u8; 0]
-}}
-
-/// Array type with {rank} dimensions and {elemtype} elements
-pub struct {rust_type}<'a> {{
+/// Array type with {rank} dimensions and {elemtype} elements, `{name}` in Futhark
+pub struct {rust_type} {{
     ptr: *mut {futhark_type},
-    pub shape: [i64; {rank}],
-    ctx: &'a Context,
+    shape: [i64; {rank}],
+    ctx: Context,
 }}
 
-impl<'a> {rust_type}<'a> {{
+impl {rust_type} {{
     /// Create a new array of `dims` dimensions and initialize it with the values from `data`
-    pub fn new(ctx: &'a Context, dims: [i64; {rank}], data: impl AsRef<[{elemtype}]>) -> std::result::Result<Self, Error> {{
-        let size: i64 = dims.iter().product();
+    pub fn new{bits}(ctx: &Context, dims: [i64; {rank}], data: impl AsRef<[{rust_elemtype}]>) -> std::result::Result<Self, Error> {{
+        let size = checked_element_count(&dims)?;
         let data = data.as_ref();
-        if data.len() as i64 != size {{
-            return Err(Error::InvalidShape)
+        if data.len() != size {{
+            return Err(Error::InvalidShape {{ expected: size, got: data.len() }})
         }}
         let ptr = unsafe {{
-            {new_fn}(ctx.context, data.as_ptr(), {dim_params})
+            {new_fn}(ctx.inner.context, data.as_ptr(), {dim_params})
         }};
         if ptr.is_null() {{ return Err(Error::NullPtr); }}
-        ctx.auto_sync();
-        Ok(Self {{
+        let arr = Self {{
             ptr: ptr as *mut _,
             shape: dims,
-            ctx,
-        }})
+            ctx: ctx.clone(),
+        }};
+        ctx.auto_sync()?;
+        Ok(arr)
+    }}
+
+    /// Create a new array like `new{bits}`, with `usize` dimensions that are checked to fit in an `i64`
+    pub fn new_usize_dims{bits}(ctx: &Context, dims: [usize; {rank}], data: impl AsRef<[{rust_elemtype}]>) -> std::result::Result<Self, Error> {{
+        let mut checked = [0i64; {rank}];
+        for (d, &n) in checked.iter_mut().zip(&dims) {{
+            *d = i64::try_from(n).map_err(|_| {{
+                Error::InvalidDims(dims.iter().map(|&n| i64::try_from(n).unwrap_or(i64::MAX)).collect())
+            }})?;
+        }}
+        Self::new{bits}(ctx, checked, data)
     }}
 
-    /// Get the array shape
+    /// Get the array shape, this is read from Futhark when the array is created
     pub fn shape(&self) -> &[i64; {rank}] {{
         &self.shape
     }}
 
-    /// Load values back into a slice
-    pub fn values(&self, mut data: impl AsMut<[{elemtype}]>) -> std::result::Result<(), Error> {{
-        let size: i64 = self.shape.iter().product();
+    /// Get the size of the outermost dimension
+    pub fn len(&self) -> i64 {{
+        self.shape[0]
+    }}
+
+    /// Returns `true` if the array has no elements
+    pub fn is_empty(&self) -> bool {{
+        self.element_count() == 0
+    }}
+
+    /// Get the total number of elements, the length of the slice passed to `values`
+    pub fn element_count(&self) -> usize {{
+        self.shape.iter().product::<i64>() as usize
+    }}
+
+    /// Load values back into a slice, which must have `element_count()` elements
+    pub fn values{bits}(&self, mut data: impl AsMut<[{rust_elemtype}]>) -> std::result::Result<(), Error> {{
+        let size = self.element_count();
         let data = data.as_mut();
-        if data.len() as i64 != size {{
-            return Err(Error::InvalidShape);
+        if data.len() != size {{
+            return Err(Error::InvalidShape {{ expected: size, got: data.len() }});
         }}
         let rc = unsafe {{
-            {values_fn}(self.ctx.context, self.ptr, data.as_mut_ptr())
+            {values_fn}(self.ctx.inner.context, self.ptr, data.as_mut_ptr())
         }};
         if rc != 0 {{
-            return Err(Error::Code(rc));
+            return Err(self.ctx.error(rc));
         }}
-        self.ctx.auto_sync();
+        // `data` is only written once the copy from the device has finished
+        self.ctx.sync()?;
         Ok(())
     }}
 
+    /// Load values into an existing buffer without allocating, same as `values`
+    pub fn values_into{bits}(&self, dest: &mut [{rust_elemtype}]) -> std::result::Result<(), Error> {{
+        self.values{bits}(dest)
+    }}
+
     /// Load values into a `Vec`
-    pub fn get(&self) -> std::result::Result<Vec<{elemtype}>, Error> {{
-        let size: i64 = self.shape.iter().product();
-        let mut vec = vec![{elemtype}::default(); size as usize];
-        self.values(&mut vec)?;
+    pub fn get{bits}(&self) -> std::result::Result<Vec<{rust_elemtype}>, Error> {{
+        let mut vec = vec![{rust_elemtype}::default(); self.element_count()];
+        self.values{bits}(&mut vec)?;
         Ok(vec)
     }}
 
+    /// Create a copy of the array, the C API has no copy function for arrays so the data is
+    /// copied through host memory
+    pub fn try_clone(&self) -> std::result::Result<Self, Error> {{
+        let data = self.get{bits}()?;
+        Self::new{bits}(&self.ctx, self.shape, data)
+    }}
+
+    /// Load values into a new `Vec`, same as `get`
+    pub fn values_vec{bits}(&self) -> std::result::Result<Vec<{rust_elemtype}>, Error> {{
+        self.get{bits}()
+    }}
+
+    /// Load the values and shape into host memory
+    pub fn to_host{bits}(&self) -> std::result::Result<(Vec<{rust_elemtype}>, [i64; {rank}]), Error> {{
+        Ok((self.get{bits}()?, self.shape))
+    }}
+
 
     #[allow(unused)]
-    fn from_ptr(ctx: &'a Context, ptr: *mut {futhark_type}) -> Self {{
-        let len_ptr = unsafe {{ futhark_shape_{elemtype}_{rank}d(ctx.context, ptr) }};
+    fn from_ptr(ctx: &Context, ptr: *mut {futhark_type}) -> Self {{
+        let len_ptr = unsafe {{ futhark_shape_{elemtype}_{rank}d(ctx.inner.context, ptr) }};
         let mut shape = [0i64; {rank}];
         unsafe {{
             for (i, s) in shape.iter_mut().enumerate() {{
                 *s = *len_ptr.add(i);
             }}
         }}
-        Self {{ ctx, shape, ptr }}
+        Self {{ ctx: ctx.clone(), shape, ptr }}
     }}
 }}
 
 
-impl<'a> Drop for {rust_type}<'a> {{
+/// Only the shape is printed, reading the values would have to wait for Futhark
+impl std::fmt::Debug for {rust_type} {{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{
+        f.debug_struct("{rust_type}").field("shape", &self.shape).finish()
+    }}
+}}
+
+impl Drop for {rust_type} {{
     fn drop(&mut self){{
         unsafe {{
-            futhark_free_{elemtype}_{rank}d(self.ctx.context, self.ptr as *mut _);
+            futhark_free_{elemtype}_{rank}d(self.ctx.inner.context, self.ptr as *mut _);
         }}
     }}
 }}
 
+// sys
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct {futhark_type} {{
+    _private: [u8; 0]
+}}
+
 #[allow(unused)]
 extern "C" {{
-    fn {shape_fn}(
+    pub fn {shape_fn}(
         _: *mut futhark_context,
         _: *mut {futhark_type}
     ) -> *const i64;
 
-    fn {new_fn}(
+    pub fn {new_fn}(
         _: *mut futhark_context,
-        _: *const {elemtype},
+        _: *const {rust_elemtype},
         {new_dim_args}
     ) -> *mut {futhark_type};
 
-    fn {free_fn}(
+    pub fn {free_fn}(
         _: *mut futhark_context,
         _: *mut {futhark_type}
     ) -> std::os::raw::c_int;
 
-    fn {values_fn}(
+    pub fn {values_fn}(
         _: *mut futhark_context,
         _: *mut {futhark_type},
-        _: *mut {elemtype}
+        _: *mut {rust_elemtype}
     ) -> std::os::raw::c_int;
 }}
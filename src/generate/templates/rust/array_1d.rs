@@ -0,0 +1,6 @@
+impl {rust_type} {{
+    /// Create a new array with the values from `data`, the length is taken from the slice
+    pub fn from_slice{bits}(ctx: &Context, data: &[{rust_elemtype}]) -> std::result::Result<Self, Error> {{
+        Self::new{bits}(ctx, [data.len() as i64], data)
+    }}
+}}
@@ -0,0 +1,15 @@
+impl {rust_type} {{
+    /// CUDA device pointer to the elements of the array, valid while the array is alive. Call
+    /// `Context::sync` before using it outside of Futhark
+    pub fn device_ptr(&self) -> u64 {{
+        unsafe {{ {values_raw_fn}(self.ctx.inner.context, self.ptr) }}
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {values_raw_fn}(
+        _: *mut futhark_context,
+        _: *mut {futhark_type},
+    ) -> u64;
+}}
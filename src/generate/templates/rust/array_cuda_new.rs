@@ -0,0 +1,24 @@
+impl {rust_type} {{
+    /// Create an array that uses existing device memory holding the elements, without copying it
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a device allocation of the context's device with room for all elements.
+    /// Futhark doesn't take ownership, so the allocation has to stay valid until the array is
+    /// freed and is not freed by Futhark. Sync any work writing to it before calling this
+    pub unsafe fn from_raw(ctx: &Context, ptr: u64, dims: [i64; {rank}]) -> std::result::Result<Self, Error> {{
+        checked_element_count(&dims)?;
+        let arr = {new_raw_fn}(ctx.inner.context, ptr, {dim_params});
+        if arr.is_null() {{ return Err(Error::NullPtr); }}
+        Ok(Self::from_ptr(ctx, arr))
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {new_raw_fn}(
+        _: *mut futhark_context,
+        _: u64,
+        {new_raw_dims}
+    ) -> *mut {futhark_type};
+}}
@@ -0,0 +1,7 @@
+/// Shape and values of an array in host memory, arrays are serialized in this form and need to
+/// be deserialized into it before uploading them with `from_data`, since that requires a `Context`
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ArrayData<T> {
+    pub shape: Vec<i64>,
+    pub data: Vec<T>,
+}
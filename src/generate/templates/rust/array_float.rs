@@ -0,0 +1,15 @@
+impl {rust_type} {{
+    /// Assert that both arrays have the same shape and that the elements differ by at most
+    /// `tol`, for use in tests
+    ///
+    /// This panics if the values can't be read
+    #[track_caller]
+    pub fn assert_approx_eq(&self, other: &Self, tol: {rust_elemtype}) {{
+        assert_eq!(self.shape, other.shape, "array shapes differ");
+        let a = self.get().expect("unable to read array values");
+        let b = other.get().expect("unable to read array values");
+        for (i, (x, y)) in a.iter().zip(&b).enumerate() {{
+            assert!(x == y || (x - y).abs() <= tol, "arrays differ at index {{i}}: {{x}} != {{y}}, tolerance {{tol}}");
+        }}
+    }}
+}}
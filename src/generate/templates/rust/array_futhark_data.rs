@@ -0,0 +1,14 @@
+impl {rust_type} {{
+    /// Read an array in the Futhark binary data format, other values may follow it in `reader`
+    pub fn read_futhark_data(ctx: &Context, mut reader: impl std::io::Read) -> std::result::Result<Self, Error> {{
+        let dims: [i64; {rank}] = futhark_data::read_header(&mut reader, b"{data_type}")?;
+        let data = futhark_data::read_values(&mut reader, checked_element_count(&dims)?)?;
+        Self::new{bits}(ctx, dims, data)
+    }}
+
+    /// Write the array in the Futhark binary data format
+    pub fn write_futhark_data(&self, mut writer: impl std::io::Write) -> std::result::Result<(), Error> {{
+        futhark_data::write_header(&mut writer, b"{data_type}", &self.shape)?;
+        futhark_data::write_values(&mut writer, &self.get{bits}()?)
+    }}
+}}
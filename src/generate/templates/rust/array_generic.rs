@@ -0,0 +1,23 @@
+impl sealed::Sealed for {rust_type} {{}}
+
+impl GenericArray<{rust_elemtype}, {rank}> for {rust_type} {{
+    fn new(ctx: &Context, dims: [i64; {rank}], data: impl AsRef<[{rust_elemtype}]>) -> std::result::Result<Self, Error> {{
+        {rust_type}::new(ctx, dims, data)
+    }}
+
+    fn shape(&self) -> &[i64; {rank}] {{
+        {rust_type}::shape(self)
+    }}
+
+    fn values(&self, data: impl AsMut<[{rust_elemtype}]>) -> std::result::Result<(), Error> {{
+        {rust_type}::values(self, data)
+    }}
+
+    fn get(&self) -> std::result::Result<Vec<{rust_elemtype}>, Error> {{
+        {rust_type}::get(self)
+    }}
+}}
+
+impl ArrayElement<{rank}> for {rust_elemtype} {{
+    type Array = {rust_type};
+}}
@@ -0,0 +1,29 @@
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Array types of the program, for code that is generic over the element type and rank. This is
+/// only implemented by the generated array types
+pub trait GenericArray<T, const R: usize>: Sized + sealed::Sealed {
+    /// Create a new array of `dims` dimensions and initialize it with the values from `data`
+    fn new(ctx: &Context, dims: [i64; R], data: impl AsRef<[T]>) -> std::result::Result<Self, Error>;
+
+    /// Get the shape of the array
+    fn shape(&self) -> &[i64; R];
+
+    /// Load the values of the array into `data`
+    fn values(&self, data: impl AsMut<[T]>) -> std::result::Result<(), Error>;
+
+    /// Get the values of the array as a new `Vec`
+    fn get(&self) -> std::result::Result<Vec<T>, Error>;
+}
+
+/// Element types with an array of rank `R` in the program
+pub trait ArrayElement<const R: usize>: Sized {
+    /// The generated array type
+    type Array: GenericArray<Self, R>;
+}
+
+/// Array of `T` with rank `R`, e.g. `Array<f32, 2>` is `ArrayF32D2`. Using an element type or rank
+/// the program has no array of is a compile error
+pub type Array<T, const R: usize> = <T as ArrayElement<R>>::Array;
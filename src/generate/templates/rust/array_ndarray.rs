@@ -0,0 +1,23 @@
+impl {rust_type} {{
+    /// Create a new array from an `ndarray` view, views that aren't in standard layout are copied first
+    pub fn from_ndarray{bits}(ctx: &Context, view: &ndarray::ArrayView<{rust_elemtype}, {ndarray_dim}>) -> std::result::Result<Self, Error> {{
+        if view.ndim() != {rank} {{
+            return Err(Error::InvalidDims(view.shape().iter().map(|&d| d as i64).collect()));
+        }}
+        let dims: [i64; {rank}] = std::array::from_fn(|i| view.shape()[i] as i64);
+        match view.as_slice() {{
+            Some(data) => Self::new{bits}(ctx, dims, data),
+            None => Self::new{bits}(ctx, dims, view.iter().cloned().collect::<Vec<_>>()),
+        }}
+    }}
+
+    /// Copy the values into a new `ndarray` array with the same shape
+    pub fn to_ndarray{bits}(&self) -> std::result::Result<ndarray::Array<{rust_elemtype}, {ndarray_dim}>, Error> {{
+        let dims: Vec<usize> = self.shape.iter().map(|&d| d as usize).collect();
+        let data = self.get{bits}()?;
+        let got = data.len();
+        ndarray::ArrayD::from_shape_vec(ndarray::IxDyn(&dims), data)
+            .and_then(|a| a.into_dimensionality::<{ndarray_dim}>())
+            .map_err(|_| Error::InvalidShape {{ expected: self.element_count(), got }})
+    }}
+}}
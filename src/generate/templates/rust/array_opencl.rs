@@ -0,0 +1,18 @@
+impl {rust_type} {{
+    /// OpenCL buffer holding the elements of the array, valid while the array is alive
+    ///
+    /// The elements start at offset 0 of the buffer in row-major order, but the buffer may be
+    /// larger than `element_count()` elements since Futhark reuses allocations. Use the
+    /// queue from `Context::command_queue`, or call `Context::sync` before using it elsewhere
+    pub fn cl_mem(&self) -> *mut std::ffi::c_void {{
+        unsafe {{ {values_raw_fn}(self.ctx.inner.context, self.ptr) }}
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {values_raw_fn}(
+        _: *mut futhark_context,
+        _: *mut {futhark_type},
+    ) -> *mut std::ffi::c_void;
+}}
@@ -0,0 +1,24 @@
+impl {rust_type} {{
+    /// Create an array that uses an existing OpenCL buffer holding the elements, without copying it
+    ///
+    /// # Safety
+    ///
+    /// `mem` must be a buffer of the context's OpenCL context, holding all elements in row-major
+    /// order starting at offset 0. Futhark doesn't take ownership, so the buffer has to stay
+    /// valid until the array is freed. Finish any commands writing to it before calling this
+    pub unsafe fn from_cl_mem(ctx: &Context, mem: *mut std::ffi::c_void, dims: [i64; {rank}]) -> std::result::Result<Self, Error> {{
+        checked_element_count(&dims)?;
+        let arr = {new_raw_fn}(ctx.inner.context, mem, {dim_params});
+        if arr.is_null() {{ return Err(Error::NullPtr); }}
+        Ok(Self::from_ptr(ctx, arr))
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {new_raw_fn}(
+        _: *mut futhark_context,
+        _: *mut std::ffi::c_void,
+        {new_raw_dims}
+    ) -> *mut {futhark_type};
+}}
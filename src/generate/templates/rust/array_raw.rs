@@ -0,0 +1,25 @@
+impl {rust_type} {{
+    /// Pointer to the elements of the array in host memory, it is only valid while both the
+    /// array and the context are alive
+    pub fn as_ptr(&self) -> *const {rust_elemtype} {{
+        unsafe {{ {values_raw_fn}(self.ctx.inner.context, self.ptr) }}
+    }}
+
+    /// Borrow the elements of the array without copying them, the context is synced first
+    pub fn as_slice{bits}(&self) -> std::result::Result<&[{rust_elemtype}], Error> {{
+        self.ctx.sync()?;
+        let len = self.element_count();
+        if len == 0 {{ return Ok(&[]) }}
+        let ptr = self.as_ptr();
+        if ptr.is_null() {{ return Err(Error::NullPtr) }}
+        Ok(unsafe {{ std::slice::from_raw_parts(ptr, len) }})
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {values_raw_fn}(
+        _: *mut futhark_context,
+        _: *mut {futhark_type},
+    ) -> *mut {rust_elemtype};
+}}
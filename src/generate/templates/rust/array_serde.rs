@@ -0,0 +1,18 @@
+impl {rust_type} {{
+    /// Create a new array from values in host memory, e.g. a deserialized `ArrayData`
+    pub fn from_data(ctx: &Context, data: &ArrayData<{rust_elemtype}>) -> std::result::Result<Self, Error> {{
+        let dims: [i64; {rank}] = data.shape.as_slice().try_into().map_err(|_| Error::InvalidDims(data.shape.clone()))?;
+        Self::new{bits}(ctx, dims, &data.data)
+    }}
+
+    /// Copy the shape and values into host memory
+    pub fn to_data(&self) -> std::result::Result<ArrayData<{rust_elemtype}>, Error> {{
+        Ok(ArrayData {{ shape: self.shape.to_vec(), data: self.get{bits}()? }})
+    }}
+}}
+
+impl serde::Serialize for {rust_type} {{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {{
+        self.to_data().map_err(serde::ser::Error::custom)?.serialize(serializer)
+    }}
+}}
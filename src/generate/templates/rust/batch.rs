@@ -0,0 +1,27 @@
+/// Entry point calls made in `Context::batch`, the methods have the same parameters as the ones on
+/// `Context` but only store the results
+pub struct Batch<'a> {{
+    ctx: &'a Context,
+    results: Vec<BatchResult>,
+}}
+
+/// Results of an entry point called in a batch, with a variant for every entry point
+#[derive(Debug)]
+pub enum BatchResult {{
+    {variants}
+}}
+
+impl Context {{
+    /// Call entry points through the `Batch` passed to `f`, without syncing in between, and sync
+    /// once at the end. The results are returned in the order of the calls, the first failing
+    /// call stops the batch with `Error::Batch` holding its index
+    pub fn batch(
+        &self,
+        f: impl FnOnce(&mut Batch) -> std::result::Result<(), Error>,
+    ) -> std::result::Result<Vec<BatchResult>, Error> {{
+        let mut batch = Batch {{ ctx: self, results: Vec::new() }};
+        f(&mut batch)?;
+        self.sync()?;
+        Ok(batch.results)
+    }}
+}}
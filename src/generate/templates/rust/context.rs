@@ -1,31 +1,99 @@
 #[derive(Debug)]
 pub enum Error {{
-    Code(std::os::raw::c_int),
+    /// A Futhark function failed with this return code and error message
+    Code(std::os::raw::c_int, String),
+    /// A Futhark function returned a NULL pointer
     NullPtr,
-    InvalidShape,
+    /// The number of elements doesn't match the shape of the array
+    InvalidShape {{ expected: usize, got: usize }},
+    /// An array dimension is negative, or the number of elements doesn't fit in an `i64`
+    InvalidDims(Vec<i64>),
+    /// A sum type was accessed as a variant it doesn't hold
+    InvalidVariant,
+    /// A string passed to the options contains a NUL byte
+    Nul(std::ffi::NulError),
+    /// The context couldn't be created, with the Futhark error message
+    Init(String),
+    /// The program has no tuning parameter with this name
+    UnknownTuningParam(String),
+    /// A line of a tuning file isn't a `name=value` pair
+    InvalidTuningFile(String),
+    /// Reading or writing Futhark data failed
+    Io(std::io::Error),
+    /// The input isn't a value of the expected type in the Futhark binary data format
+    InvalidData(String),
+    /// A call in `Context::batch` failed, with the index of the call
+    Batch {{ index: usize, error: Box<Error> }},
 }}
 
 impl std::fmt::Display for Error {{
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {{
         match self {{
-            Error::Code(code) => write!(fmt, "Futhark error code: {{code}}"),
+            Error::Code(code, msg) => write!(fmt, "Futhark error code {{code}}: {{msg}}"),
             Error::NullPtr => write!(fmt, "NULL pointer encountered"),
-            Error::InvalidShape => write!(fmt, "Invalid image shape"),
+            Error::InvalidShape {{ expected, got }} => {{
+                write!(fmt, "Invalid shape: expected {{expected}} elements, got {{got}}")
+            }}
+            Error::InvalidDims(dims) => write!(fmt, "Invalid array dimensions: {{dims:?}}"),
+            Error::InvalidVariant => write!(fmt, "Sum type holds a different variant"),
+            Error::Nul(e) => write!(fmt, "Invalid C string: {{e}}"),
+            Error::Init(msg) => write!(fmt, "Unable to create context: {{msg}}"),
+            Error::UnknownTuningParam(name) => write!(fmt, "Unknown tuning parameter: {{name}}"),
+            Error::InvalidTuningFile(msg) => write!(fmt, "Invalid tuning file: {{msg}}"),
+            Error::Io(e) => write!(fmt, "I/O error: {{e}}"),
+            Error::InvalidData(msg) => write!(fmt, "Invalid Futhark data: {{msg}}"),
+            Error::Batch {{ index, error }} => write!(fmt, "Call {{index}} of the batch failed: {{error}}"),
         }}
     }} 
 }}
 
-impl std::error::Error for Error {{}}
+impl std::error::Error for Error {{
+    fn source(&self) -> std::option::Option<&(dyn std::error::Error + 'static)> {{
+        match self {{
+            Error::Nul(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Batch {{ error, .. }} => Some(error),
+            _ => None,
+        }}
+    }}
+}}
+
+impl From<std::ffi::NulError> for Error {{
+    fn from(e: std::ffi::NulError) -> Self {{
+        Error::Nul(e)
+    }}
+}}
+
+impl From<std::io::Error> for Error {{
+    fn from(e: std::io::Error) -> Self {{
+        Error::Io(e)
+    }}
+}}
+
+/// Number of elements in an array with the given dimensions
+fn checked_element_count(dims: &[i64]) -> std::result::Result<usize, Error> {{
+    dims.iter()
+        .try_fold(1i64, |n, &d| if d < 0 {{ None }} else {{ n.checked_mul(d) }})
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or_else(|| Error::InvalidDims(dims.to_vec()))
+}}
+
+/// Names of the tuning parameters of the program
+const TUNING_PARAM_NAMES: &[&str] = &[{tuning_param_names}];
+
+/// Hash of the generated C code, used to name cache files
+const PROGRAM_HASH: &str = "{program_hash}";
 
 #[derive(Debug, Clone)]
 pub struct Options {{
     debug: bool,
     profile: bool,
     logging: bool,
-    num_threads: u32,
     cache_file: std::option::Option<std::ffi::CString>,
-    device: std::option::Option<std::ffi::CString>,
     auto_sync: bool,
+    tuning_params: Vec<(std::ffi::CString, usize)>,
+    nul_error: std::option::Option<std::ffi::NulError>,
+    {backend_fields}
 }}
 
 impl Default for Options {{
@@ -41,13 +109,33 @@ impl Options {{
             debug: false,
             profile: false,
             logging: false,
-            num_threads: 0,
             cache_file: None,
-            device: None,
             auto_sync: true,
+            tuning_params: Vec::new(),
+            nul_error: None,
+            {backend_defaults}
         }}
     }}
 
+    /// Convert `s` to a C string, an invalid string is reported by `build`
+    fn cstring(&mut self, s: impl Into<Vec<u8>>) -> std::option::Option<std::ffi::CString> {{
+        match std::ffi::CString::new(s) {{
+            Ok(s) => Some(s),
+            Err(e) => {{
+                self.nul_error.get_or_insert(e);
+                None
+            }}
+        }}
+    }}
+
+    fn path_cstring(&mut self, path: &std::path::Path) -> std::option::Option<std::ffi::CString> {{
+        #[cfg(unix)]
+        let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+        #[cfg(not(unix))]
+        let bytes = path.to_string_lossy().into_owned().into_bytes();
+        self.cstring(bytes)
+    }}
+
     /// Enable debug
     pub fn debug(mut self) -> Self {{
         self.debug = true;
@@ -66,109 +154,214 @@ impl Options {{
         self
     }}
 
-    /// Set Futhark cache file
-    pub fn cache_file(mut self, s: impl AsRef<str>) -> Self {{
-        self.cache_file = Some(std::ffi::CString::new(s.as_ref()).expect("Invalid cache file"));
+    /// Enable or disable profiling, see `Context::report`
+    pub fn profiling(mut self, profile: bool) -> Self {{
+        self.profile = profile;
+        self
+    }}
+
+    /// Enable or disable debugging output, this also enables logging
+    pub fn debugging(mut self, debug: bool) -> Self {{
+        self.debug = debug;
+        self
+    }}
+
+    /// Enable or disable logging
+    pub fn logging(mut self, logging: bool) -> Self {{
+        self.logging = logging;
+        self
+    }}
+
+    /// Set Futhark cache file, compiled kernels are stored there and reused by later runs
+    pub fn cache_file(mut self, path: impl AsRef<std::path::Path>) -> Self {{
+        self.cache_file = self.path_cstring(path.as_ref());
         self
     }}
 
+    /// Use a cache file in `dir` named after a hash of the program, so different programs
+    /// can share a cache directory
+    pub fn cache_in(self, dir: impl AsRef<std::path::Path>) -> Self {{
+        let name = format!("futhark-{{PROGRAM_HASH}}.cache");
+        self.cache_file(dir.as_ref().join(name))
+    }}
+
+    /// Sync the context after every operation, this is enabled by default
     pub fn auto_sync(mut self, sync: bool) -> Self {{
         self.auto_sync = sync;
         self
     }}
 
+    /// Set a tuning parameter, the names of the parameters are listed in the `tuning_param` module
+    pub fn tuning_param(mut self, name: impl AsRef<str>, value: usize) -> Self {{
+        if let Some(name) = self.cstring(name.as_ref()) {{
+            self.tuning_params.push((name, value));
+        }}
+        self
+    }}
+
+    /// Set a tuning parameter, returning an error if the program has no parameter named `name`
+    pub fn set_tuning_param(self, name: impl AsRef<str>, value: usize) -> std::result::Result<Self, Error> {{
+        let name = name.as_ref();
+        if !TUNING_PARAM_NAMES.contains(&name) {{
+            return Err(Error::UnknownTuningParam(name.to_string()));
+        }}
+        Ok(self.tuning_param(name, value))
+    }}
+
+    /// Set the tuning parameters from a file written by `futhark autotune`, which has one
+    /// `name=value` pair per line. Parameters that don't belong to this program are skipped
+    pub fn load_tuning_file(mut self, path: impl AsRef<std::path::Path>) -> std::result::Result<Self, Error> {{
+        let src = std::fs::read_to_string(path.as_ref())?;
+        for line in src.lines().map(str::trim).filter(|l| !l.is_empty()) {{
+            let (name, value) = line
+                .split_once('=')
+                .and_then(|(name, value)| Some((name.trim(), value.trim().parse::<usize>().ok()?)))
+                .ok_or_else(|| Error::InvalidTuningFile(format!("expected name=value, found `{{line}}`")))?;
+            if !TUNING_PARAM_NAMES.contains(&name) {{
+                {warn_unknown_tuning_param}
+                continue;
+            }}
+            self = self.tuning_param(name, value);
+        }}
+        Ok(self)
+    }}
+
 
     {backend_options}
+
+    /// Create a context using these options, same as `Context::new_with_options`
+    pub fn build(self) -> std::result::Result<Context, Error> {{
+        Context::new_with_options(self)
+    }}
 }}
 
-/// Futhark context
+/// Futhark context, the library was compiled with the `{backend}` backend
+///
+/// This is a reference counted handle, clones refer to the same context. Arrays and other values
+/// hold a clone, so the context is only freed after every value created with it is dropped
+#[derive(Clone)]
 pub struct Context {{
+    inner: std::sync::Arc<ContextInner>,
+    auto_sync: bool,
+}}
+
+/// Owns the Futhark context and everything it uses
+struct ContextInner {{
     config: *mut futhark_context_config,
     context: *mut futhark_context,
-    auto_sync: bool,
     _cache_file: std::option::Option<std::ffi::CString>,
+    _logger: Logger,
 }}
 
 impl Context {{
     /// Create a new context with default options
     pub fn new() -> std::result::Result<Self, Error> {{
-        unsafe {{
-            let config = futhark_context_config_new();
-            if config.is_null() {{ return Err(Error::NullPtr) }}
-            let context = futhark_context_new(config);
-            if context.is_null() {{
-                futhark_context_config_free(config);
-                return Err(Error::NullPtr);
-            }}
-            Ok(Context {{ config, context, auto_sync: true, _cache_file: None }})
-        }}
+        Context::new_with_options(Options::new())
+    }}
+
+    /// Start configuring a new context, see `Options`
+    pub fn builder() -> Options {{
+        Options::new()
     }}
 
     /// Create a new context with custom options
-    pub fn new_with_options(options: Options) -> std::result::Result<Self, Error> {{
+    pub fn new_with_options(mut options: Options) -> std::result::Result<Self, Error> {{
+        if let Some(e) = options.nul_error.take() {{
+            return Err(Error::Nul(e));
+        }}
         unsafe {{
             let config = futhark_context_config_new();
             if config.is_null() {{ return Err(Error::NullPtr) }}
+            {configure_tuning_params}
 
             futhark_context_config_set_debugging(config, options.debug as std::os::raw::c_int);
             futhark_context_config_set_profiling(config, options.profile as std::os::raw::c_int);
             futhark_context_config_set_logging(config, options.logging as std::os::raw::c_int);
 
+            for (name, value) in &options.tuning_params {{
+                if futhark_context_config_set_tuning_param(config, name.as_ptr(), *value) != 0 {{
+                    futhark_context_config_free(config);
+                    return Err(Error::UnknownTuningParam(name.to_string_lossy().to_string()));
+                }}
+            }}
+
             if let Some(c) = &options.cache_file {{
                 futhark_context_config_set_cache_file(config, c.as_ptr());
             }}
 
-            {configure_num_threads}
-            {configure_set_device}
+            {configure_backend}
 
             let context = futhark_context_new(config);
             if context.is_null() {{
                 futhark_context_config_free(config);
                 return Err(Error::NullPtr);
             }}
-            Ok(Context {{ config, context, auto_sync: options.auto_sync, _cache_file: options.cache_file }})
+
+            // Initialization errors, e.g. no matching device, are reported on the new context
+            let e = futhark_context_get_error(context);
+            if !e.is_null() {{
+                let msg = std::ffi::CStr::from_ptr(e).to_string_lossy().to_string();
+                free(e as *mut _);
+                futhark_context_free(context);
+                futhark_context_config_free(config);
+                return Err(Error::Init(msg));
+            }}
+            let _logger = Logger::new(context, options.debug || options.logging);
+            let inner = ContextInner {{ config, context, _cache_file: options.cache_file, _logger }};
+            Ok(Context {{ inner: std::sync::Arc::new(inner), auto_sync: options.auto_sync }})
         }}
     }}
 
-    /// Sync the context, if `auto_sync` is enabled this shouldn't be needed
-    pub fn sync(&self) {{
-        unsafe {{ futhark_context_sync(self.context); }}
+    /// Wait for all pending operations to finish, errors from asynchronous operations, e.g.
+    /// kernels launched by an entry point, are reported here
+    pub fn sync(&self) -> std::result::Result<(), Error> {{
+        let rc = unsafe {{ futhark_context_sync(self.inner.context) }};
+        if rc != 0 {{ return Err(self.error(rc)) }}
+        Ok(())
     }}
 
     /// Sync if `auto_sync` is enabled, otherwise this is a noop
-    pub fn auto_sync(&self) {{
+    pub fn auto_sync(&self) -> std::result::Result<(), Error> {{
         if self.auto_sync {{
-            self.sync();
+            self.sync()?;
         }}
+        Ok(())
     }}
 
-    /// Clear Futhark caches
+    /// Create an `Error::Code` with the current error message of the context
+    fn error(&self, rc: std::os::raw::c_int) -> Error {{
+        Error::Code(rc, self.get_error().unwrap_or_default())
+    }}
+
+    /// Release the memory Futhark keeps for reuse after arrays are freed, along with its other
+    /// caches, e.g. to return device memory to the driver between phases of a long-running
+    /// program. Arrays that are still alive are unaffected
     pub fn clear_caches(&self) -> std::result::Result<(), Error> {{
         let rc = unsafe {{
-            futhark_context_clear_caches(self.context)
+            futhark_context_clear_caches(self.inner.context)
         }};
-        if rc != 0 {{ return Err(Error::Code(rc)) }}
+        if rc != 0 {{ return Err(self.error(rc)) }}
         Ok(())
     }}
 
-    /// Pause Futhark profiling
+    /// Pause Futhark profiling, nothing is recorded until `unpause_profiling` is called
     pub fn pause_profiling(&self) {{
         unsafe {{
-            futhark_context_pause_profiling(self.context);
+            futhark_context_pause_profiling(self.inner.context);
         }}
     }}
 
-    /// Resume profiling
+    /// Resume profiling after `pause_profiling`
     pub fn unpause_profiling(&self) {{
         unsafe {{
-            futhark_context_unpause_profiling(self.context);
+            futhark_context_unpause_profiling(self.inner.context);
         }}
     }}
 
     /// Get the last error message or None
     pub fn get_error(&self) -> std::option::Option<String> {{
         unsafe {{
-            let s = futhark_context_get_error(self.context);
+            let s = futhark_context_get_error(self.inner.context);
             if s.is_null() {{ return None }}
             let r = std::ffi::CStr::from_ptr(s).to_string_lossy().to_string();
             free(s as *mut _);
@@ -176,18 +369,90 @@ impl Context {{
         }}
     }}
 
-    pub fn report(&self) -> std::option::Option<String> {{
+    /// Get the profiling report, this includes timings for each kernel when profiling is enabled
+    pub fn report(&self) -> std::result::Result<String, Error> {{
         unsafe {{
-            let s = futhark_context_report(self.context);
-            if s.is_null() {{ return None }}
+            let s = futhark_context_report(self.inner.context);
+            if s.is_null() {{ return Err(Error::NullPtr) }}
             let r = std::ffi::CStr::from_ptr(s).to_string_lossy().to_string();
             free(s as *mut _);
-            Some(r)
+            Ok(r)
         }}
     }}
+
+    /// Get the peak memory usage in bytes of each memory space, e.g. `default` or `device`
+    ///
+    /// Futhark only keeps track of the peak, memory given back with `clear_caches` isn't
+    /// subtracted from it
+    pub fn peak_memory_usage(&self) -> std::result::Result<std::collections::BTreeMap<String, u64>, Error> {{
+        Ok(parse_peak_memory(&self.report()?))
+    }}
 }}
 
-impl Drop for Context {{
+/// Read the memory usage from a report, which is either a JSON object containing
+/// `"memory":{{"space":bytes,...}}` or, with older compilers, text containing lines like
+/// `Peak memory usage for space 'device': 1024 bytes.`. Spaces are named as in the JSON
+/// format, e.g. `default` and `device`
+fn parse_peak_memory(report: &str) -> std::collections::BTreeMap<String, u64> {{
+    let space_name = |desc: &str| {{
+        let desc = desc.trim().trim_matches('"');
+        desc.strip_prefix("space '")
+            .and_then(|s| s.strip_suffix('\''))
+            .or_else(|| desc.strip_suffix(" space"))
+            .unwrap_or(desc)
+            .to_string()
+    }};
+    let mut usage = std::collections::BTreeMap::new();
+    if let Some(i) = report.find("\"memory\"") {{
+        let body = report[i..]
+            .split_once('{{')
+            .and_then(|(_, rest)| rest.split_once('}}'))
+            .map(|(body, _)| body)
+            .unwrap_or_default();
+        for item in body.split(',') {{
+            if let Some((space, bytes)) = item.rsplit_once(':') {{
+                if let Ok(bytes) = bytes.trim().parse() {{
+                    usage.insert(space_name(space), bytes);
+                }}
+            }}
+        }}
+    }} else {{
+        for line in report.lines() {{
+            let usage_line = line.trim().strip_prefix("Peak memory usage for ").and_then(|l| l.rsplit_once(": "));
+            if let Some((space, bytes)) = usage_line {{
+                if let Some(Ok(bytes)) = bytes.split_whitespace().next().map(str::parse) {{
+                    usage.insert(space_name(space), bytes);
+                }}
+            }}
+        }}
+    }}
+    usage
+}}
+
+/// Results of an entry point called through its `_async` variant, Futhark may still be computing
+/// them when the call returns so they are only available after `wait`
+#[must_use = "the results are only available after calling `wait`"]
+pub struct PendingResults<T> {{
+    ctx: Context,
+    results: T,
+}}
+
+impl<T> PendingResults<T> {{
+    /// Wait for the context to finish all pending operations and return the results, errors from
+    /// the entry point are reported here
+    pub fn wait(self) -> std::result::Result<T, Error> {{
+        self.ctx.sync()?;
+        Ok(self.results)
+    }}
+}}
+
+// Futhark contexts are thread safe, every API function takes the context lock, so a context
+// can be shared between threads. The only caveat is that the message read by `get_error` may
+// come from a failure on another thread
+unsafe impl Send for ContextInner {{}}
+unsafe impl Sync for ContextInner {{}}
+
+impl Drop for ContextInner {{
     fn drop(&mut self) {{
         unsafe {{
             futhark_context_sync(self.context);
@@ -197,76 +462,83 @@ impl Drop for Context {{
     }}
 }}
 
+// sys
 #[repr(C)]
 #[allow(non_camel_case_types)]
-struct futhark_context_config {{
+pub struct futhark_context_config {{
     _private: [u8; 0]
 }}
 
 #[repr(C)]
 #[allow(non_camel_case_types)]
-struct futhark_context {{
+pub struct futhark_context {{
     _private: [u8; 0]
 }}
 
 extern "C" {{
-    fn futhark_context_config_new() -> *mut futhark_context_config;
-    fn futhark_context_config_free(
+    pub fn futhark_context_config_new() -> *mut futhark_context_config;
+    pub fn futhark_context_config_free(
         _: *mut futhark_context_config
     );
-    fn futhark_context_config_set_debugging(
+    pub fn futhark_context_config_set_debugging(
         _: *mut futhark_context_config,
         _: std::os::raw::c_int
     );
 
-    fn futhark_context_config_set_profiling(
+    pub fn futhark_context_config_set_profiling(
         _: *mut futhark_context_config,
         _: std::os::raw::c_int
     );
 
-    fn futhark_context_config_set_logging(
+    pub fn futhark_context_config_set_logging(
         _: *mut futhark_context_config,
         _: std::os::raw::c_int
     );
 
-    fn futhark_context_config_set_cache_file(
+    pub fn futhark_context_config_set_cache_file(
         _: *mut futhark_context_config,
         _: *const std::os::raw::c_char,
     );
 
-    fn futhark_context_new(
+    pub fn futhark_context_new(
         _: *mut futhark_context_config
     ) -> *mut futhark_context;
 
-    fn futhark_context_free(
+    pub fn futhark_context_free(
         _: *mut futhark_context
     );
 
-    fn futhark_context_sync(
+    pub fn futhark_context_sync(
         _: *mut futhark_context,
     ) -> std::os::raw::c_int;
 
-    fn futhark_context_clear_caches(
+    pub fn futhark_context_clear_caches(
         _: *mut futhark_context,
     ) -> std::os::raw::c_int;
 
-    fn futhark_context_pause_profiling(
+    pub fn futhark_context_pause_profiling(
         _: *mut futhark_context
     );
 
-    fn futhark_context_unpause_profiling(
+    pub fn futhark_context_unpause_profiling(
         _: *mut futhark_context
     );
 
-    fn futhark_context_get_error(
+    pub fn futhark_context_get_error(
         _: *mut futhark_context
     ) -> *mut std::os::raw::c_char;
 
-    fn futhark_context_report(
+    pub fn futhark_context_report(
         _: *mut futhark_context
     ) -> *mut std::os::raw::c_char;
 
-    fn free(_: *mut std::ffi::c_void);
+    pub fn futhark_context_config_set_tuning_param(
+        _: *mut futhark_context_config,
+        _: *const std::os::raw::c_char,
+        _: usize,
+    ) -> std::os::raw::c_int;
+
+    pub fn free(_: *mut std::ffi::c_void);
 
     {backend_extern_functions}
 }}
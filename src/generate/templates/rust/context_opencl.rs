@@ -0,0 +1,43 @@
+impl Context {
+    /// The `cl_command_queue` used by Futhark, for sharing buffers with other OpenCL code
+    ///
+    /// # Safety
+    ///
+    /// The queue belongs to the context, it must not be released and is only valid while the
+    /// context is alive
+    pub unsafe fn command_queue(&self) -> *mut std::ffi::c_void {
+        futhark_context_get_command_queue(self.inner.context)
+    }
+
+    /// The `cl_context` of the command queue, or `Error::Code` if OpenCL can't report it
+    ///
+    /// # Safety
+    ///
+    /// Same as `command_queue`, the OpenCL context must not be released
+    pub unsafe fn cl_context(&self) -> std::result::Result<*mut std::ffi::c_void, Error> {
+        const CL_QUEUE_CONTEXT: u32 = 0x1090;
+        let mut out = std::ptr::null_mut::<std::ffi::c_void>();
+        let rc = clGetCommandQueueInfo(
+            self.command_queue(),
+            CL_QUEUE_CONTEXT,
+            std::mem::size_of_val(&out),
+            &mut out as *mut _ as *mut std::ffi::c_void,
+            std::ptr::null_mut(),
+        );
+        if rc != 0 { return Err(Error::Code(rc, "clGetCommandQueueInfo failed".to_string())) }
+        Ok(out)
+    }
+}
+
+// sys
+extern "C" {
+    pub fn futhark_context_get_command_queue(_: *mut futhark_context) -> *mut std::ffi::c_void;
+
+    pub fn clGetCommandQueueInfo(
+        _: *mut std::ffi::c_void,
+        _: u32,
+        _: usize,
+        _: *mut std::ffi::c_void,
+        _: *mut usize,
+    ) -> std::os::raw::c_int;
+}
@@ -1,22 +1,46 @@
 impl Context {{
     /// Entry point: {entry_name}
-    pub fn {entry_name}(&self, {entry_params}) -> Result<{entry_return_type}, Error> {{
+    ///
+    /// Futhark signature: `{entry_signature}`{entry_doc}
+    pub fn {fn_name}(&self, {entry_params}) -> Result<{entry_return_type}, Error> {{
+        let pending = self.{async_fn}({arg_names})?;
+        self.auto_sync()?;
+        {sync_return}
+    }}
+
+    /// Entry point: {entry_name}, without waiting for Futhark to finish
+    ///
+    /// The results are returned once `wait` is called on the `PendingResults`, which syncs the
+    /// context. Meanwhile the calling thread can prepare the next inputs{entry_doc}
+    pub fn {async_fn}(&self, {entry_params}) -> Result<PendingResults<{entry_return_type}>, Error> {{
         {out_decl}
         let rc = unsafe {{
-            futhark_entry_{entry_name}(self.context, {call_args})
+            {entry_fn}(self.inner.context, {call_args})
         }};
-        if rc != 0 {{ return Err(Error::Code(rc)); }}
+        if rc != 0 {{ return Err(self.error(rc)); }}
     
-        #[allow(unused_unsafe)]
-        unsafe {{
-            Ok({entry_return})
+        {entry_results}
+        Ok(PendingResults {{ ctx: self.clone(), results }})
+    }}
+}}
+
+impl Batch<'_> {{
+    /// Call `{entry_name}` as part of the batch, see `Context::{fn_name}`
+    pub fn {fn_name}(&mut self, {entry_params}) -> Result<(), Error> {{
+        match self.ctx.{async_fn}({arg_names}) {{
+            Ok(pending) => {{
+                {batch_push}
+                Ok(())
+            }}
+            Err(error) => Err(Error::Batch {{ index: self.results.len(), error: Box::new(error) }}),
         }}
     }}
 }}
 
+// sys
 extern "C" {{
-    fn {entry_fn}(
+    pub fn {entry_fn}(
         _: *mut futhark_context,
         {futhark_entry_params}
     ) -> std::os::raw::c_int;
-}}
\ No newline at end of file
+}}
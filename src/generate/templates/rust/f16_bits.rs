@@ -0,0 +1,52 @@
+/// Futhark `f16` value as its bit pattern, used in place of `half::f16` when `futhark-bindgen`
+/// is built without the `half` feature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct F16Bits(pub u16);
+
+impl F16Bits {
+    /// Convert to `f32`, every `f16` value is exactly representable
+    pub fn to_f32(self) -> f32 {
+        let sign = if self.0 & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exp = (self.0 >> 10) & 0x1f;
+        let man = (self.0 & 0x3ff) as u32;
+        match exp {
+            0 => sign * man as f32 * f32::powi(2.0, -24),
+            0x1f if man == 0 => sign * f32::INFINITY,
+            0x1f => f32::from_bits(0x7fc00000 | (self.0 as u32 & 0x8000) << 16 | man << 13),
+            _ => f32::from_bits((self.0 as u32 & 0x8000) << 16 | (exp as u32 + 112) << 23 | man << 13),
+        }
+    }
+
+    /// Convert from `f32`, rounding to the nearest `f16` value with ties to even
+    pub fn from_f32(x: f32) -> Self {
+        let bits = x.to_bits();
+        let sign = (bits >> 16) as u16 & 0x8000;
+        let exp = ((bits >> 23) & 0xff) as i32;
+        let man = bits & 0x7fffff;
+        if exp == 0xff {
+            return F16Bits(sign | if man == 0 { 0x7c00 } else { 0x7e00 });
+        }
+
+        // Exponent of the result, values that don't fit in a normal `f16` are rounded to
+        // subnormals or zero
+        let e = exp - 112;
+        if e >= 0x1f {
+            return F16Bits(sign | 0x7c00);
+        }
+        let m = man | 0x800000;
+        let shift = if e > 0 { 13 } else { 14 - e };
+        if shift > 24 {
+            return F16Bits(sign);
+        }
+        let mut h = m >> shift;
+        let rest = m & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        if rest > half || (rest == half && h & 1 == 1) {
+            h += 1;
+        }
+        // Rounding up can carry into the exponent, which gives the right result
+        let base = if e > 0 { ((e - 1) as u32) << 10 } else { 0 };
+        F16Bits(sign | (base + h) as u16)
+    }
+}
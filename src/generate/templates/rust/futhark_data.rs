@@ -0,0 +1,195 @@
+/// Reading and writing values in the Futhark binary data format, used by `futhark test`,
+/// `futhark bench` and `futhark dataset -b`
+pub mod futhark_data {
+    use super::Error;
+    use std::io::{Read, Write};
+
+    /// Scalar types that can be read and written in the binary data format
+    pub trait Element: Copy {
+        /// Name of the type in the header of a value
+        const TYPE: &'static [u8; 4];
+
+        #[doc(hidden)]
+        const SIZE: usize;
+
+        #[doc(hidden)]
+        fn from_le(bytes: &[u8]) -> Self;
+
+        #[doc(hidden)]
+        fn to_le(self, out: &mut Vec<u8>);
+    }
+
+    macro_rules! element {
+        ($t:ty, $name:literal) => {
+            impl Element for $t {
+                const TYPE: &'static [u8; 4] = $name;
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn from_le(bytes: &[u8]) -> Self {
+                    <$t>::from_le_bytes(bytes.try_into().unwrap())
+                }
+
+                fn to_le(self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes())
+                }
+            }
+        };
+    }
+
+    element!(i8, b"  i8");
+    element!(i16, b" i16");
+    element!(i32, b" i32");
+    element!(i64, b" i64");
+    element!(u8, b"  u8");
+    element!(u16, b" u16");
+    element!(u32, b" u32");
+    element!(u64, b" u64");
+    element!(f32, b" f32");
+    element!(f64, b" f64");
+
+    impl Element for bool {
+        const TYPE: &'static [u8; 4] = b"bool";
+        const SIZE: usize = 1;
+
+        fn from_le(bytes: &[u8]) -> Self {
+            bytes[0] != 0
+        }
+
+        fn to_le(self, out: &mut Vec<u8>) {
+            out.push(self as u8)
+        }
+    }
+
+    fn describe(ty: &[u8], rank: usize) -> String {
+        format!("{}{}", "[]".repeat(rank), String::from_utf8_lossy(ty).trim())
+    }
+
+    /// Read the header of a value, checking the type and rank and returning the dimensions
+    pub(crate) fn read_header<const N: usize>(reader: &mut impl Read, ty: &[u8; 4]) -> std::result::Result<[i64; N], Error> {
+        let mut byte = [0u8];
+        loop {
+            reader.read_exact(&mut byte)?;
+            if !byte[0].is_ascii_whitespace() {
+                break;
+            }
+        }
+        if byte[0] != b'b' {
+            return Err(Error::InvalidData("only the binary format is supported".to_string()));
+        }
+
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header)?;
+        if header[0] != 2 {
+            return Err(Error::InvalidData(format!("unsupported version {}", header[0])));
+        }
+        let (rank, got) = (header[1] as usize, &header[2..]);
+        if rank != N || got != ty {
+            return Err(Error::InvalidData(format!(
+                "expected {}, found {}",
+                describe(ty, N),
+                describe(got, rank)
+            )));
+        }
+
+        let mut dims = [0; N];
+        for d in &mut dims {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            *d = i64::from_le_bytes(bytes);
+        }
+        Ok(dims)
+    }
+
+    /// Read `n` values following the header
+    pub(crate) fn read_values<T: Element>(reader: &mut impl Read, n: usize) -> std::result::Result<Vec<T>, Error> {
+        let len = n
+            .checked_mul(T::SIZE)
+            .ok_or_else(|| Error::InvalidData(format!("too many elements: {n}")))?;
+        let mut bytes = Vec::new();
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(bytes.chunks_exact(T::SIZE).map(T::from_le).collect())
+    }
+
+    pub(crate) fn write_header(writer: &mut impl Write, ty: &[u8; 4], dims: &[i64]) -> std::result::Result<(), Error> {
+        writer.write_all(&[b'b', 2, dims.len() as u8])?;
+        writer.write_all(ty)?;
+        for d in dims {
+            writer.write_all(&d.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_values<T: Element>(writer: &mut impl Write, values: &[T]) -> std::result::Result<(), Error> {
+        let mut bytes = Vec::with_capacity(values.len() * T::SIZE);
+        for x in values {
+            x.to_le(&mut bytes);
+        }
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Check that `bytes` consists of one or more complete values, this is how opaque values are
+    /// stored and the restore functions don't know the length of their input. If `expected` is
+    /// given the values must also have exactly those types and ranks
+    #[allow(unused)]
+    pub(crate) fn check_values(
+        mut bytes: &[u8],
+        expected: Option<&[(&[u8; 4], u8)]>,
+    ) -> std::result::Result<(), Error> {
+        let invalid = || Error::InvalidData("truncated or corrupted value".to_string());
+        if bytes.is_empty() {
+            return Err(invalid());
+        }
+        let mut found = Vec::new();
+        while !bytes.is_empty() {
+            if bytes.len() < 7 || bytes[0] != b'b' || bytes[1] != 2 {
+                return Err(invalid());
+            }
+            let rank = bytes[2] as usize;
+            let size = match &bytes[3..7] {
+                b"  i8" | b"  u8" | b"bool" => 1,
+                b" i16" | b" u16" | b" f16" => 2,
+                b" i32" | b" u32" | b" f32" => 4,
+                b" i64" | b" u64" | b" f64" => 8,
+                _ => return Err(invalid()),
+            };
+            found.push((&bytes[3..7], bytes[2]));
+            if bytes.len() < 7 + rank * 8 {
+                return Err(invalid());
+            }
+            let (dims, rest) = bytes[7..].split_at(rank * 8);
+            let dims: Vec<i64> = dims
+                .chunks_exact(8)
+                .map(|d| i64::from_le_bytes(d.try_into().unwrap()))
+                .collect();
+            let len = super::checked_element_count(&dims)
+                .ok()
+                .and_then(|n| n.checked_mul(size))
+                .filter(|&len| len <= rest.len())
+                .ok_or_else(invalid)?;
+            bytes = &rest[len..];
+        }
+        if let Some(expected) = expected {
+            let matches = found.len() == expected.len()
+                && found.iter().zip(expected).all(|(f, e)| f.0 == e.0 && f.1 == e.1);
+            if !matches {
+                return Err(Error::InvalidData("stored value has the wrong type".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a scalar value
+    pub fn read_scalar<T: Element>(mut reader: impl Read) -> std::result::Result<T, Error> {
+        read_header::<0>(&mut reader, T::TYPE)?;
+        Ok(read_values(&mut reader, 1)?[0])
+    }
+
+    /// Write a scalar value
+    pub fn write_scalar<T: Element>(mut writer: impl Write, value: T) -> std::result::Result<(), Error> {
+        write_header(&mut writer, T::TYPE, &[])?;
+        write_values(&mut writer, &[value])
+    }
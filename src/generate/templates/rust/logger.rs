@@ -0,0 +1,69 @@
+/// Forwards the Futhark log to the `log` crate, through a pipe read by a separate thread
+struct Logger {{
+    #[cfg(unix)]
+    inner: std::option::Option<(*mut std::ffi::c_void, std::thread::JoinHandle<()>)>,
+}}
+
+impl Logger {{
+    #[cfg(unix)]
+    unsafe fn new(context: *mut futhark_context, enabled: bool) -> Logger {{
+        if !enabled {{ return Logger {{ inner: None }} }}
+        let mut fds = [0; 2];
+        if pipe(fds.as_mut_ptr()) != 0 {{ return Logger {{ inner: None }} }}
+        let file = fdopen(fds[1], b"w\0".as_ptr() as *const std::os::raw::c_char);
+        if file.is_null() {{
+            close(fds[0]);
+            close(fds[1]);
+            return Logger {{ inner: None }};
+        }}
+
+        // Line buffered, so messages show up as they are written
+        setvbuf(file, std::ptr::null_mut(), 1, 0);
+        let reader = <std::fs::File as std::os::unix::io::FromRawFd>::from_raw_fd(fds[0]);
+        let thread = std::thread::spawn(move || {{
+            use std::io::BufRead;
+            for line in std::io::BufReader::new(reader).lines() {{
+                match line {{
+                    Ok(line) => log::debug!(target: "futhark::{program}", "{{line}}"),
+                    Err(_) => break,
+                }}
+            }}
+        }});
+        futhark_context_set_logging_file(context, file);
+        Logger {{ inner: Some((file, thread)) }}
+    }}
+
+    #[cfg(not(unix))]
+    unsafe fn new(_context: *mut futhark_context, _enabled: bool) -> Logger {{
+        Logger {{}}
+    }}
+}}
+
+impl Drop for Logger {{
+    fn drop(&mut self) {{
+        #[cfg(unix)]
+        if let Some((file, thread)) = self.inner.take() {{
+            unsafe {{ fclose(file); }}
+            let _ = thread.join();
+        }}
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn futhark_context_set_logging_file(
+        _: *mut futhark_context,
+        _: *mut std::ffi::c_void,
+    );
+
+    pub fn pipe(_: *mut std::os::raw::c_int) -> std::os::raw::c_int;
+    pub fn fdopen(_: std::os::raw::c_int, _: *const std::os::raw::c_char) -> *mut std::ffi::c_void;
+    pub fn setvbuf(
+        _: *mut std::ffi::c_void,
+        _: *mut std::os::raw::c_char,
+        _: std::os::raw::c_int,
+        _: usize,
+    ) -> std::os::raw::c_int;
+    pub fn fclose(_: *mut std::ffi::c_void) -> std::os::raw::c_int;
+    pub fn close(_: std::os::raw::c_int) -> std::os::raw::c_int;
+}}
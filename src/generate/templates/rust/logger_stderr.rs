@@ -0,0 +1,9 @@
+/// Futhark writes its log to stderr, enable the `log` feature of futhark-bindgen to forward it
+/// to the `log` crate instead
+struct Logger;
+
+impl Logger {
+    unsafe fn new(_context: *mut futhark_context, _enabled: bool) -> Logger {
+        Logger
+    }
+}
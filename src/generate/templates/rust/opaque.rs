@@ -1,33 +1,95 @@
-#[repr(C)]
-#[allow(non_camel_case_types)]
-struct {futhark_type} {{
-    _private: [u8; 0]
+/// Futhark type `{description}`
+pub struct {rust_type} {{
+    data: *mut {futhark_type},
+    ctx: Context,
 }}
 
-extern "C" {{
-    fn {free_fn}(
-        _: *mut futhark_context,
-        _: *mut {futhark_type}
-    ) -> std::os::raw::c_int;
-}}
+impl {rust_type} {{
+    #[allow(unused)]
+    fn from_ptr(ctx: &Context, data: *mut {futhark_type}) -> Self {{
+        Self {{ ctx: ctx.clone(), data }}
+    }}
 
-/// Futhark type
-pub struct {rust_type}<'a> {{
-    data: *mut {futhark_type},
-    ctx: &'a Context,
+    /// Serialize the value to bytes that can be passed to `restore`, the representation is
+    /// specific to the program that created it
+    pub fn store(&self) -> std::result::Result<Vec<u8>, Error> {{
+        let mut n = 0;
+        let rc = unsafe {{
+            {store_fn}(self.ctx.inner.context, self.data, std::ptr::null_mut(), &mut n)
+        }};
+        if rc != 0 {{ return Err(self.ctx.error(rc)); }}
+        let mut bytes = vec![0u8; n];
+        let mut p = bytes.as_mut_ptr() as *mut std::ffi::c_void;
+        let rc = unsafe {{
+            {store_fn}(self.ctx.inner.context, self.data, &mut p, &mut n)
+        }};
+        if rc != 0 {{ return Err(self.ctx.error(rc)); }}
+        // The bytes are only written once the copy from the device has finished
+        self.ctx.sync()?;
+        Ok(bytes)
+    }}
+
+    /// Restore a value from bytes returned by `store`{restore_doc}
+    pub {restore_unsafe}fn restore(ctx: &Context, bytes: &[u8]) -> std::result::Result<Self, Error> {{
+        futhark_data::check_values(bytes, {stored_layout})?;
+        let data = unsafe {{
+            {restore_fn}(ctx.inner.context, bytes.as_ptr() as *const std::ffi::c_void)
+        }};
+        if data.is_null() {{
+            return Err(Error::InvalidData(ctx.get_error().unwrap_or_default()));
+        }}
+        let data = Self::from_ptr(ctx, data);
+        // `bytes` is only borrowed for this call, the copy to the device must finish before it returns
+        ctx.sync()?;
+        Ok(data)
+    }}
+
+    /// Create a copy of the value, the C API has no copy function so this goes through host
+    /// memory using `store` and `restore`
+    pub fn try_clone(&self) -> std::result::Result<Self, Error> {{
+        let bytes = self.store()?;
+        // The bytes were just stored from a value of this type
+        #[allow(unused_unsafe)]
+        unsafe {{ Self::restore(&self.ctx, &bytes) }}
+    }}
 }}
 
-impl<'a> {rust_type}<'a> {{
-    #[allow(unused)]
-    fn from_ptr(ctx: &'a Context, data: *mut {futhark_type}) -> Self {{
-        Self {{ ctx, data }}
+impl std::fmt::Debug for {rust_type} {{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{
+        f.debug_tuple("{rust_type}").field(&self.data).finish()
     }}
 }}
 
-impl<'a> Drop for {rust_type}<'a> {{
+impl Drop for {rust_type} {{
     fn drop(&mut self) {{
         unsafe {{
-            {free_fn}(self.ctx.context, self.data);
+            {free_fn}(self.ctx.inner.context, self.data);
         }}
     }}
 }}
+
+// sys
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct {futhark_type} {{
+    _private: [u8; 0]
+}}
+
+extern "C" {{
+    pub fn {free_fn}(
+        _: *mut futhark_context,
+        _: *mut {futhark_type}
+    ) -> std::os::raw::c_int;
+
+    pub fn {store_fn}(
+        _: *mut futhark_context,
+        _: *const {futhark_type},
+        _: *mut *mut std::ffi::c_void,
+        _: *mut usize,
+    ) -> std::os::raw::c_int;
+
+    pub fn {restore_fn}(
+        _: *mut futhark_context,
+        _: *const std::ffi::c_void,
+    ) -> *mut {futhark_type};
+}}
@@ -0,0 +1,49 @@
+impl {rust_type} {{
+    /// Get the array shape
+    pub fn shape(&self) -> [i64; {rank}] {{
+        let ptr = unsafe {{ {shape_fn}(self.ctx.inner.context, self.data) }};
+        let mut shape = [0i64; {rank}];
+        unsafe {{
+            for (i, s) in shape.iter_mut().enumerate() {{
+                *s = *ptr.add(i);
+            }}
+        }}
+        shape
+    }}
+
+    /// Get the size of the outermost dimension
+    pub fn len(&self) -> i64 {{
+        self.shape()[0]
+    }}
+
+    /// Returns `true` if the array has no elements
+    pub fn is_empty(&self) -> bool {{
+        self.shape().contains(&0)
+    }}
+
+    /// Get a copy of the element at the given index
+    pub fn get(&self, {index_params}) -> std::result::Result<{elem_rust_type}, Error> {{
+        let mut out = std::ptr::null_mut();
+        let rc = unsafe {{
+            {index_fn}(self.ctx.inner.context, &mut out, self.data, {index_args})
+        }};
+        if rc != 0 {{ return Err(self.ctx.error(rc)); }}
+        self.ctx.auto_sync()?;
+        Ok({elem_rust_type}::from_ptr(&self.ctx, out))
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {shape_fn}(
+        _: *mut futhark_context,
+        _: *const {futhark_type}
+    ) -> *const i64;
+
+    pub fn {index_fn}(
+        _: *mut futhark_context,
+        _: *mut *mut {elem_futhark_type},
+        _: *const {futhark_type},
+        {index_extern_params}
+    ) -> std::os::raw::c_int;
+}}
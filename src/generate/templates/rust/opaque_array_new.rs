@@ -0,0 +1,28 @@
+impl {rust_type} {{
+    /// Create a new array of `dims` dimensions from the elements in `data`
+    pub fn new(ctx: &Context, dims: [i64; {rank}], data: &[&{elem_rust_type}]) -> std::result::Result<Self, Error> {{
+        let size = checked_element_count(&dims)?;
+        if data.len() != size {{
+            return Err(Error::InvalidShape {{ expected: size, got: data.len() }});
+        }}
+        let ptrs: Vec<*const {elem_futhark_type}> = data.iter().map(|x| x.data as *const _).collect();
+        let mut out = std::ptr::null_mut();
+        let rc = unsafe {{
+            {new_fn}(ctx.inner.context, &mut out, ptrs.as_ptr(), {dim_args})
+        }};
+        if rc != 0 {{ return Err(ctx.error(rc)); }}
+        let out = Self {{ data: out, ctx: ctx.clone() }};
+        ctx.auto_sync()?;
+        Ok(out)
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {new_fn}(
+        _: *mut futhark_context,
+        _: *mut *mut {futhark_type},
+        _: *const *const {elem_futhark_type},
+        {new_dim_params}
+    ) -> std::os::raw::c_int;
+}}
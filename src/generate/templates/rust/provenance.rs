@@ -0,0 +1,10 @@
+/// Backend the Futhark program was compiled with
+pub const FUTHARK_BACKEND: &str = "{backend}";
+
+/// Futhark compiler that compiled the program, from `futhark --version`, or the version in the
+/// manifest when the bindings were generated from an existing manifest
+pub const FUTHARK_COMPILER_VERSION: &str = {compiler_version:?};
+
+/// SHA-256 hash of the Futhark source file, empty if it couldn't be read when generating the
+/// bindings
+pub const FUTHARK_SOURCE_SHA256: &str = "{source_sha256}";
@@ -1,20 +1,22 @@
-impl<'a> {rust_type}<'a> {{
+impl {rust_type} {{
     /// Create new {rust_type}
-    pub fn new(ctx: &'a Context, {new_params}) -> std::result::Result<Self, Error> {{
+    pub fn new(ctx: &Context, {new_params}) -> std::result::Result<Self, Error> {{
         unsafe {{
             let mut out = std::ptr::null_mut();
-            let rc = {new_fn}(ctx.context, &mut out, {new_call_args});
-            if rc != 0 {{ return Err(Error::Code(rc)); }}
-            ctx.auto_sync();
-            Ok(Self {{ data: out, ctx }})
+            let rc = {new_fn}(ctx.inner.context, &mut out, {new_call_args});
+            if rc != 0 {{ return Err(ctx.error(rc)); }}
+            let out = Self {{ data: out, ctx: ctx.clone() }};
+            ctx.auto_sync()?;
+            Ok(out)
         }}
     }}
 }}
 
+// sys
 extern "C" {{
-    fn {new_fn}(
+    pub fn {new_fn}(
         _: *mut futhark_context,
         _: *mut *mut {futhark_type},
         {new_extern_params}
     ) -> std::os::raw::c_int;
-}}
\ No newline at end of file
+}}
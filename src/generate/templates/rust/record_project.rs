@@ -1,23 +1,25 @@
-impl<'a> {rust_type}<'a> {{
-    /// Get {field_name} field
-    pub fn get_{field_name}(&self) -> Result<{rust_field_type}, Error> {{
+impl {rust_type} {{
+    /// Get {field_name} field, array and opaque fields are returned as new values that are
+    /// freed independently of `self`
+    pub fn get_{field_name}(&self) -> std::result::Result<{rust_field_type}, Error> {{
         let mut out = std::mem::MaybeUninit::zeroed();
         let rc = unsafe {{
             {project_fn}(
-                self.ctx.context,
+                self.ctx.inner.context,
                 out.as_mut_ptr(),
                 self.data
             )
         }};
-        if rc != 0 {{ return Err(Error::Code(rc)); }}
-        self.ctx.auto_sync();
+        if rc != 0 {{ return Err(self.ctx.error(rc)); }}
+        {sync}
         let out = unsafe {{ out.assume_init() }};
         {output}
     }}
 }}
 
+// sys
 extern "C" {{
-    fn {project_fn}(
+    pub fn {project_fn}(
         _: *mut futhark_context,
         _: *mut {futhark_field_type},
         _: *const {futhark_type}
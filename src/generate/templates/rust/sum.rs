@@ -0,0 +1,24 @@
+/// Variants of {rust_type}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum {rust_type}Variant {{
+    {variants}
+}}
+
+impl {rust_type} {{
+    /// Get the active variant
+    pub fn variant(&self) -> {rust_type}Variant {{
+        let n = unsafe {{ {variant_fn}(self.ctx.inner.context, self.data) }};
+        match n {{
+            {variant_arms}
+            _ => unreachable!("invalid variant index {{n}}"),
+        }}
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {variant_fn}(
+        _: *mut futhark_context,
+        _: *const {futhark_type}
+    ) -> std::os::raw::c_int;
+}}
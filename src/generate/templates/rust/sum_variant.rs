@@ -0,0 +1,51 @@
+impl {rust_type} {{
+    /// Create new {rust_type} holding the {variant_name} variant
+    pub fn new_{variant_fn_name}(ctx: &Context, {new_params}) -> std::result::Result<Self, Error> {{
+        unsafe {{
+            let mut out = std::ptr::null_mut();
+            let rc = {construct_fn}(ctx.inner.context, &mut out, {new_call_args});
+            if rc != 0 {{ return Err(ctx.error(rc)); }}
+            let out = Self {{ data: out, ctx: ctx.clone() }};
+            ctx.auto_sync()?;
+            Ok(out)
+        }}
+    }}
+
+    /// Get the payload of the {variant_name} variant, returns `Error::InvalidVariant` if a
+    /// different variant is held
+    pub fn get_{variant_fn_name}(&self) -> std::result::Result<{payload_type}, Error> {{
+        if self.variant() != {rust_type}Variant::{variant_ident} {{
+            return Err(Error::InvalidVariant);
+        }}
+        {out_decl}
+        let rc = unsafe {{
+            {destruct_fn}(self.ctx.inner.context, {destruct_call_args}self.data)
+        }};
+        if rc != 0 {{ return Err(self.ctx.error(rc)); }}
+        {sync}
+        #[allow(unused_unsafe)]
+        unsafe {{
+            Ok({payload})
+        }}
+    }}
+    /// Take the payload of the {variant_name} variant, returning `self` if a different variant
+    /// is held
+    pub fn into_{variant_fn_name}(self) -> std::result::Result<{payload_type}, Self> {{
+        self.get_{variant_fn_name}().map_err(|_| self)
+    }}
+}}
+
+// sys
+extern "C" {{
+    pub fn {construct_fn}(
+        _: *mut futhark_context,
+        _: *mut *mut {futhark_type},
+        {construct_extern_params}
+    ) -> std::os::raw::c_int;
+
+    pub fn {destruct_fn}(
+        _: *mut futhark_context,
+        {destruct_extern_params}
+        _: *const {futhark_type}
+    ) -> std::os::raw::c_int;
+}}
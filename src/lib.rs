@@ -1,19 +1,35 @@
 pub(crate) use std::collections::BTreeMap;
 
+mod autotune;
 mod compiler;
 mod error;
 pub(crate) mod generate;
+mod link;
 pub mod manifest;
 mod package;
+mod sha256;
+mod version;
 
+pub use autotune::AutotuneOptions;
 pub use compiler::Compiler;
 pub use error::Error;
-pub use generate::{Config, Generate, OCaml, Rust};
+pub use generate::{Config, Generate, OCaml, RenameStyle, Rust, Visibility};
+pub use link::{CudaRuntime, LinkOptions};
 pub use manifest::Manifest;
 pub use package::Package;
+pub use version::Version;
+
+/// Print a warning, using `cargo:warning` when running inside of a build script
+pub(crate) fn warn(msg: &str) {
+    if std::env::var_os("OUT_DIR").is_some() {
+        println!("cargo:warning={msg}");
+    } else {
+        eprintln!("warning: {msg}");
+    }
+}
 
 /// `Backend` is used to select a backend when running the `futhark` executable
-#[derive(Debug, serde::Deserialize, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone, Copy)]
 pub enum Backend {
     /// Sequential C backend: `futhark c`
     ///
@@ -48,7 +64,7 @@ pub enum Backend {
 
     /// HIP backend: `futhark hip`
     ///
-    /// Requires a C compiler
+    /// Requires ROCm and a C compiler
     #[serde(rename = "hip")]
     HIP,
 }
@@ -66,16 +82,21 @@ impl Backend {
         }
     }
 
+    /// All available backends
+    pub fn all() -> &'static [Backend] {
+        &[
+            Backend::C,
+            Backend::CUDA,
+            Backend::OpenCL,
+            Backend::Multicore,
+            Backend::ISPC,
+            Backend::HIP,
+        ]
+    }
+
     /// Return the backend specified by the given name if valid
     pub fn from_name(name: &str) -> Option<Backend> {
-        match name.to_ascii_lowercase().as_str() {
-            "c" => Some(Backend::C),
-            "cuda" => Some(Backend::CUDA),
-            "opencl" => Some(Backend::OpenCL),
-            "multicore" => Some(Backend::Multicore),
-            "ispc" => Some(Backend::ISPC),
-            _ => None,
-        }
+        name.parse().ok()
     }
 
     /// Get the backend from the `FUTHARK_BACKEND` environment variable
@@ -87,15 +108,93 @@ impl Backend {
     }
 
     /// Returns the C libraries that need to be linked for a backend
+    #[deprecated(note = "use `Backend::required_libs`, which takes the target into account")]
     pub fn required_c_libs(&self) -> &'static [&'static str] {
+        self.c_libs()
+    }
+
+    fn c_libs(&self) -> &'static [&'static str] {
         match self {
             Backend::CUDA => &["cuda", "cudart", "nvrtc", "m"],
             Backend::OpenCL => &["OpenCL", "m"],
             Backend::Multicore | Backend::ISPC => &["pthread", "m"],
-            Backend::HIP => &["hiprtc", "amdhip64"],
-            _ => &[],
+            Backend::HIP => &["hiprtc", "amdhip64", "m"],
+            Backend::C => &["m"],
+        }
+    }
+
+    /// Returns the libraries that need to be linked for a backend when building
+    /// for `target`, which should be a target triple such as the `TARGET` variable
+    /// passed to build scripts
+    ///
+    /// ```
+    /// use futhark_bindgen::{Backend, RequiredLib};
+    ///
+    /// let libs = Backend::OpenCL.required_libs("aarch64-apple-darwin");
+    /// assert_eq!(libs, [RequiredLib::Framework("OpenCL")]);
+    /// assert_eq!(libs[0].link_directive(), "cargo:rustc-link-lib=framework=OpenCL");
+    /// ```
+    pub fn required_libs(&self, target: &str) -> Vec<RequiredLib> {
+        // The C library, libm and pthreads are all provided by the wasm toolchains,
+        // and none of the GPU runtimes exist there
+        if target.starts_with("wasm32") {
+            return Vec::new();
+        }
+
+        // libm is part of the system library on macOS and of the C runtime with MSVC,
+        // Futhark uses Windows threads instead of pthreads on Windows
+        let apple = target.contains("apple");
+        let msvc = target.ends_with("windows-msvc");
+        self.c_libs()
+            .iter()
+            .filter_map(|&lib| match lib {
+                "m" if apple || msvc => None,
+                "pthread" if msvc => None,
+                "OpenCL" if apple => Some(RequiredLib::Framework(lib)),
+                _ => Some(RequiredLib::Lib(lib)),
+            })
+            .collect()
+    }
+}
+
+/// Library required by a backend, see `Backend::required_libs`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RequiredLib {
+    /// Regular library
+    Lib(&'static str),
+
+    /// macOS framework
+    Framework(&'static str),
+}
+
+impl RequiredLib {
+    /// Get the name of the library
+    pub fn name(&self) -> &'static str {
+        match self {
+            RequiredLib::Lib(name) | RequiredLib::Framework(name) => name,
         }
     }
+
+    /// Get the `cargo:rustc-link-lib` directive used to link the library
+    pub fn link_directive(&self) -> String {
+        match self {
+            RequiredLib::Lib(name) => format!("cargo:rustc-link-lib={name}"),
+            RequiredLib::Framework(name) => format!("cargo:rustc-link-lib=framework={name}"),
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Backend, Error> {
+        let name = name.to_ascii_lowercase();
+        Backend::all()
+            .iter()
+            .find(|b| b.to_str() == name)
+            .copied()
+            .ok_or(Error::InvalidBackend(name))
+    }
 }
 
 #[cfg(feature = "build")]
@@ -106,22 +205,366 @@ impl Backend {
 /// `src` is the full path to your Futhark code
 ///
 /// `dest` is expected to be a relative path that will
-/// be appended to `$OUT_DIR`
+/// be appended to `$OUT_DIR`, an absolute path is used as-is
+///
+/// The `FUTHARK_BACKEND` environment variable can be used to override `backend`, and
+/// `FUTHARK_EXE` to select the Futhark compiler
+///
+/// This panics on failure, see `try_build` for a version that returns an error instead
 pub fn build(
     backend: Backend,
     src: impl AsRef<std::path::Path>,
     dest: impl AsRef<std::path::Path>,
 ) {
-    let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
-    let dest = std::path::PathBuf::from(&out).join(dest);
-    let lib = Compiler::new(backend, src)
-        .with_output_dir(out)
-        .compile()
-        .expect("Compilation failed");
-
-    let mut config = Config::new(&dest).expect("Unable to configure codegen");
-    let mut gen = config.detect().expect("Invalid output language");
-    gen.generate(&lib, &mut config)
-        .expect("Code generation failed");
-    lib.link();
+    try_build(backend, src, dest).unwrap_or_else(|e| panic!("{e}"));
+}
+
+#[cfg(feature = "build")]
+/// Same as `build`, but `f` can be used to customize the `cc::Build` used to compile
+/// the generated C code, see `Package::link_with`
+pub fn build_with(
+    backend: Backend,
+    src: impl AsRef<std::path::Path>,
+    dest: impl AsRef<std::path::Path>,
+    f: impl FnOnce(&mut cc::Build),
+) {
+    try_build_with(backend, src, dest, f).unwrap_or_else(|e| panic!("{e}"));
+}
+
+#[cfg(feature = "build")]
+/// Generate the bindings and link the Futhark C code, returning an error instead of panicking
+///
+/// This can be used to fall back to pre-generated bindings when the Futhark compiler
+/// isn't available, see `build` for a description of the arguments
+pub fn try_build(
+    backend: Backend,
+    src: impl AsRef<std::path::Path>,
+    dest: impl AsRef<std::path::Path>,
+) -> Result<Package, Error> {
+    try_build_with(backend, src, dest, |_| ())
+}
+
+#[cfg(feature = "build")]
+/// Same as `build_with`, but returns an error instead of panicking
+pub fn try_build_with(
+    backend: Backend,
+    src: impl AsRef<std::path::Path>,
+    dest: impl AsRef<std::path::Path>,
+    f: impl FnOnce(&mut cc::Build),
+) -> Result<Package, Error> {
+    build_package(backend, src.as_ref(), dest.as_ref(), None, f)
+}
+
+#[cfg(feature = "build")]
+/// Same as `build`, but the Futhark C code is linked into a static library called `name`,
+/// see `Package::with_lib_name`
+pub fn build_named(
+    backend: Backend,
+    src: impl AsRef<std::path::Path>,
+    dest: impl AsRef<std::path::Path>,
+    name: &str,
+) {
+    try_build_named(backend, src, dest, name).unwrap_or_else(|e| panic!("{e}"));
+}
+
+#[cfg(feature = "build")]
+/// Same as `build_named`, but returns an error instead of panicking
+pub fn try_build_named(
+    backend: Backend,
+    src: impl AsRef<std::path::Path>,
+    dest: impl AsRef<std::path::Path>,
+    name: &str,
+) -> Result<Package, Error> {
+    build_package(backend, src.as_ref(), dest.as_ref(), Some(name), |_| ())
+}
+
+#[cfg(feature = "build")]
+fn build_package(
+    backend: Backend,
+    src: &std::path::Path,
+    dest: &std::path::Path,
+    name: Option<&str>,
+    f: impl FnOnce(&mut cc::Build),
+) -> Result<Package, Error> {
+    let out = out_dir()?;
+    let dest = out.join(dest);
+    let backend = backend_override(backend)?;
+    let mut lib = Compiler::new(backend, src).with_output_dir(out).compile()?;
+    if let Some(name) = name {
+        lib = lib.with_lib_name(name);
+    }
+
+    let mut config = Config::new(&dest)?;
+    let mut gen = config
+        .detect()
+        .ok_or_else(|| Error::UnsupportedOutputLanguage(dest.clone()))?;
+    gen.generate(&lib, &mut config)?;
+    lib.try_link_with(f)?;
+    Ok(lib)
+}
+
+#[cfg(feature = "build")]
+/// Replace `backend` with the value of `FUTHARK_BACKEND` if it is set, this also
+/// tells cargo to rerun the build script when the environment selects another compiler
+fn backend_override(backend: Backend) -> Result<Backend, Error> {
+    println!("cargo:rerun-if-env-changed=FUTHARK_BACKEND");
+    println!("cargo:rerun-if-env-changed=FUTHARK_EXE");
+    match std::env::var("FUTHARK_BACKEND") {
+        Ok(name) => name.parse(),
+        Err(_) => Ok(backend),
+    }
+}
+
+#[cfg(feature = "build")]
+fn out_dir() -> Result<std::path::PathBuf, Error> {
+    std::env::var_os("OUT_DIR")
+        .map(std::path::PathBuf::from)
+        .ok_or_else(|| Error::MissingEnvVar("OUT_DIR".to_string()))
+}
+
+#[cfg(feature = "build")]
+/// Generate Rust bindings for several Futhark programs and link them into the same crate
+///
+/// The programs are compiled concurrently, see `Compiler::compile_all`. Each program is placed
+/// in a module named after its source file and has its own `Context`.
+/// `Error::DuplicateModule` is returned when two source files give the same module name.
+/// The exported C symbols of each program are prefixed with the module name, see
+/// `Package::with_symbol_prefix`, so that the Futhark runtimes don't collide at link time
+///
+/// `dest` is expected to be a relative path that will be appended to `$OUT_DIR`, an absolute
+/// path is used as-is
+///
+/// This panics on failure, see `try_build_many` for a version that returns an error instead
+pub fn build_many(
+    backend: Backend,
+    srcs: &[impl AsRef<std::path::Path>],
+    dest: impl AsRef<std::path::Path>,
+) {
+    try_build_many(backend, srcs, dest).unwrap_or_else(|e| panic!("{e}"));
+}
+
+#[cfg(feature = "build")]
+/// Generate Rust bindings for several Futhark programs, returning an error instead of panicking
+///
+/// See `build_many` for a description of the arguments
+pub fn try_build_many(
+    backend: Backend,
+    srcs: &[impl AsRef<std::path::Path>],
+    dest: impl AsRef<std::path::Path>,
+) -> Result<Vec<Package>, Error> {
+    let out = out_dir()?;
+    let dest = out.join(dest);
+    let backend = backend_override(backend)?;
+    let names: Vec<_> = srcs
+        .iter()
+        .map(|src| {
+            generate::rust_ident(
+                &src.as_ref()
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+            )
+        })
+        .collect();
+    let mut seen = std::collections::BTreeMap::new();
+    for (name, src) in names.iter().zip(srcs) {
+        if let Some(first) = seen.insert(name, src.as_ref()) {
+            return Err(Error::DuplicateModule {
+                name: name.clone(),
+                first: first.to_path_buf(),
+                second: src.as_ref().to_path_buf(),
+            });
+        }
+    }
+    let compilers: Vec<_> = srcs
+        .iter()
+        .map(|src| Compiler::new(backend, src).with_output_dir(&out))
+        .collect();
+
+    let mut modules = String::from("// Generated by futhark-bindgen\n\n");
+    let mut libs = Vec::new();
+    for (name, res) in names.iter().zip(Compiler::compile_all(&compilers, None)) {
+        let lib = res?.with_symbol_prefix(format!("{name}_"));
+
+        let module_dest = out.join(name).with_extension("rs");
+        let mut config = Config::new(&module_dest)?;
+        Rust::default().generate(&lib, &mut config)?;
+        lib.try_link()?;
+
+        modules.push_str(&format!(
+            "pub mod {} {{\n    include!({:?});\n}}\n\n",
+            generate::raw_ident(name),
+            module_dest.display().to_string()
+        ));
+        libs.push(lib);
+    }
+    std::fs::write(dest, modules)?;
+    Ok(libs)
+}
+
+#[cfg(feature = "build")]
+/// Generate Rust bindings into a file in the source tree and link the Futhark C code,
+/// this allows the bindings to be committed so they are available when the Futhark
+/// compiler isn't, e.g. on docs.rs
+///
+/// `path` is relative to the crate root, the file is only written when its contents change
+/// and a `cargo:warning` is printed as a reminder to commit it. When `futhark` can't be
+/// found the existing file is used as-is and nothing is linked, a warning is printed if it
+/// doesn't match the manifest left in `$OUT_DIR` by an earlier compilation
+///
+/// This panics on failure, see `try_build_pregenerated` for a version that returns an error instead
+pub fn build_pregenerated(
+    backend: Backend,
+    src: impl AsRef<std::path::Path>,
+    path: impl AsRef<std::path::Path>,
+) {
+    try_build_pregenerated(backend, src, path).unwrap_or_else(|e| panic!("{e}"));
+}
+
+#[cfg(feature = "build")]
+/// Same as `build_pregenerated`, but returns an error instead of panicking
+///
+/// Returns `None` if the Futhark compiler wasn't found and the existing bindings were used
+pub fn try_build_pregenerated(
+    backend: Backend,
+    src: impl AsRef<std::path::Path>,
+    path: impl AsRef<std::path::Path>,
+) -> Result<Option<Package>, Error> {
+    let out = out_dir()?;
+    let path = path.as_ref();
+    let backend = backend_override(backend)?;
+    let compiler = Compiler::new(backend, src).with_output_dir(&out);
+    let lib = match compiler.compile() {
+        Err(Error::ExecutableNotFound { exe }) if path.exists() => {
+            warn(&format!(
+                "{exe} not found, using pregenerated bindings from {}",
+                path.display()
+            ));
+            check_manifest_hash(path, &compiler.output().with_extension("json"));
+            return Ok(None);
+        }
+        res => res?,
+    };
+
+    if path.extension() != Some(std::ffi::OsStr::new("rs")) {
+        return Err(Error::UnsupportedOutputLanguage(path.to_path_buf()));
+    }
+    let tmp = out.join(path.file_name().unwrap_or_default());
+    let mut config = Config::new(&tmp)?;
+    Rust::default().generate(&lib, &mut config)?;
+
+    // The hash identifies the manifest, and the compiler version it contains, that the
+    // bindings were generated from
+    let manifest = std::fs::read(lib.c_file.with_extension("json"))?;
+    let contents = format!(
+        "{MANIFEST_HASH_HEADER}{}\n{}",
+        sha256::sha256_hex(&manifest),
+        std::fs::read_to_string(&tmp)?
+    );
+    if std::fs::read_to_string(path).ok().as_deref() != Some(contents.as_str()) {
+        std::fs::write(path, contents)?;
+        warn(&format!(
+            "updated pregenerated bindings in {}, remember to commit the changes",
+            path.display()
+        ));
+    }
+
+    lib.try_link()?;
+    Ok(Some(lib))
+}
+
+/// First line of pregenerated bindings, followed by the SHA-256 of the manifest
+#[cfg(feature = "build")]
+const MANIFEST_HASH_HEADER: &str = "// futhark-bindgen manifest hash: ";
+
+/// Warn if the pregenerated bindings in `path` weren't generated from `manifest`, which is
+/// left over from an earlier compilation. Nothing is checked if the manifest doesn't exist
+#[cfg(feature = "build")]
+fn check_manifest_hash(path: &std::path::Path, manifest: &std::path::Path) {
+    let Ok(manifest) = std::fs::read(manifest) else {
+        return;
+    };
+    let expected = format!("{MANIFEST_HASH_HEADER}{}", sha256::sha256_hex(&manifest));
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+    if contents.lines().next() != Some(expected.as_str()) {
+        warn(&format!(
+            "pregenerated bindings in {} were generated from a different version of the program",
+            path.display()
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn required_libs() {
+        use RequiredLib::{Framework, Lib};
+
+        let cases: &[(Backend, &str, &[RequiredLib])] = &[
+            (Backend::C, "x86_64-unknown-linux-gnu", &[Lib("m")]),
+            (Backend::C, "wasm32-unknown-emscripten", &[]),
+            (Backend::Multicore, "wasm32-wasi", &[]),
+            (Backend::C, "aarch64-apple-darwin", &[]),
+            (Backend::C, "x86_64-pc-windows-msvc", &[]),
+            (Backend::C, "x86_64-pc-windows-gnu", &[Lib("m")]),
+            (
+                Backend::Multicore,
+                "x86_64-unknown-linux-gnu",
+                &[Lib("pthread"), Lib("m")],
+            ),
+            (Backend::Multicore, "x86_64-apple-darwin", &[Lib("pthread")]),
+            (Backend::ISPC, "x86_64-pc-windows-msvc", &[]),
+            (
+                Backend::OpenCL,
+                "x86_64-unknown-linux-gnu",
+                &[Lib("OpenCL"), Lib("m")],
+            ),
+            (
+                Backend::OpenCL,
+                "aarch64-apple-darwin",
+                &[Framework("OpenCL")],
+            ),
+            (Backend::OpenCL, "x86_64-pc-windows-msvc", &[Lib("OpenCL")]),
+            (
+                Backend::CUDA,
+                "x86_64-pc-windows-msvc",
+                &[Lib("cuda"), Lib("cudart"), Lib("nvrtc")],
+            ),
+            (
+                Backend::HIP,
+                "x86_64-unknown-linux-gnu",
+                &[Lib("hiprtc"), Lib("amdhip64"), Lib("m")],
+            ),
+        ];
+        for (backend, target, libs) in cases {
+            assert_eq!(backend.required_libs(target), *libs, "{backend:?} {target}");
+        }
+    }
+
+    /// Programs that would get the same module, and so the same library name, are rejected
+    /// before compiling
+    #[cfg(feature = "build")]
+    #[test]
+    fn build_many_same_name() {
+        let out =
+            std::env::temp_dir().join(format!("futhark-bindgen-build-many-{}", std::process::id()));
+        std::env::set_var("OUT_DIR", &out);
+        let res = try_build_many(Backend::C, &["a/prog.fut", "b/prog.fut"], "lib.rs");
+        std::env::remove_var("OUT_DIR");
+        match res {
+            Err(Error::DuplicateModule {
+                name,
+                first,
+                second,
+            }) => {
+                assert_eq!(name, "prog");
+                assert_eq!(first, std::path::Path::new("a/prog.fut"));
+                assert_eq!(second, std::path::Path::new("b/prog.fut"));
+            }
+            res => panic!("expected Error::DuplicateModule, got {res:?}"),
+        }
+    }
 }
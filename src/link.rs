@@ -0,0 +1,141 @@
+/// Options used when compiling and linking the generated C code, see `Package::with_link_options`
+#[derive(Debug, Clone)]
+pub struct LinkOptions {
+    opt_level: u32,
+    debug: Option<bool>,
+    native: bool,
+    defines: Vec<(String, Option<String>)>,
+    pub(crate) link_search: Vec<std::path::PathBuf>,
+    pub(crate) cuda_runtime: CudaRuntime,
+    pub(crate) system_libs: bool,
+    c_files: Vec<std::path::PathBuf>,
+    include_dirs: Vec<std::path::PathBuf>,
+}
+
+/// How the CUDA runtime library is linked, see `LinkOptions::with_cuda_runtime`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CudaRuntime {
+    /// Link `cudart` as a shared library
+    #[default]
+    Dynamic,
+
+    /// Link `cudart_static`, along with `rt`, `dl` and `pthread` on Linux
+    Static,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        LinkOptions {
+            opt_level: 3,
+            debug: None,
+            native: false,
+            defines: Vec::new(),
+            link_search: Vec::new(),
+            cuda_runtime: CudaRuntime::Dynamic,
+            system_libs: true,
+            c_files: Vec::new(),
+            include_dirs: Vec::new(),
+        }
+    }
+}
+
+impl LinkOptions {
+    /// Create a new `LinkOptions` with the default settings
+    pub fn new() -> LinkOptions {
+        LinkOptions::default()
+    }
+
+    /// Set the optimization level used to compile the generated C code, the default is 3
+    /// regardless of the cargo profile
+    pub fn with_opt_level(mut self, level: u32) -> Self {
+        self.opt_level = level;
+        self
+    }
+
+    /// Include debug info, by default this follows the cargo profile
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = Some(debug);
+        self
+    }
+
+    /// Optimize for the CPU of the build machine: `-march=native`
+    pub fn with_native(mut self, native: bool) -> Self {
+        self.native = native;
+        self
+    }
+
+    /// Define a preprocessor macro, this can be called multiple times
+    pub fn with_define(mut self, name: impl AsRef<str>, value: Option<&str>) -> Self {
+        self.defines
+            .push((name.as_ref().to_string(), value.map(String::from)));
+        self
+    }
+
+    /// Additional directories to search for the libraries required by the backend, e.g.
+    /// the library directory of a sysroot when cross-compiling
+    pub fn with_link_search(mut self, paths: &[impl AsRef<std::path::Path>]) -> Self {
+        self.link_search = paths.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        self
+    }
+
+    /// Select how the CUDA runtime is linked when using `Backend::CUDA`
+    ///
+    /// This only affects the runtime library, the driver library and NVRTC are always
+    /// linked dynamically since there are no static versions of them
+    pub fn with_cuda_runtime(mut self, runtime: CudaRuntime) -> Self {
+        self.cuda_runtime = runtime;
+        self
+    }
+
+    /// Link the libraries required by the backend, see `Backend::required_libs`, this is
+    /// enabled by default
+    ///
+    /// When disabled the generated C code is still compiled and linked, but the build script
+    /// becomes responsible for making the symbols of the backend libraries available, e.g.
+    /// by linking them itself or by providing a loader that opens them at runtime
+    pub fn with_system_libs(mut self, system_libs: bool) -> Self {
+        self.system_libs = system_libs;
+        self
+    }
+
+    /// Additional C files to compile into the same library as the generated code, these can
+    /// include the generated header and call the functions it declares
+    pub fn with_c_files(mut self, files: &[impl AsRef<std::path::Path>]) -> Self {
+        self.c_files = files.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        self
+    }
+
+    /// Additional include directories, the directory containing the generated header is
+    /// always included
+    pub fn with_include_dirs(mut self, dirs: &[impl AsRef<std::path::Path>]) -> Self {
+        self.include_dirs = dirs.iter().map(|p| p.as_ref().to_path_buf()).collect();
+        self
+    }
+
+    /// Apply the options to `build`, flags from `FUTHARK_BINDGEN_CFLAGS` are added last
+    #[cfg(feature = "build")]
+    pub(crate) fn apply(&self, build: &mut cc::Build) {
+        build.opt_level(self.opt_level);
+        if let Some(debug) = self.debug {
+            build.debug(debug);
+        }
+        if self.native {
+            build.flag_if_supported("-march=native");
+        }
+        for (name, value) in &self.defines {
+            build.define(name, value.as_deref());
+        }
+        build.includes(&self.include_dirs);
+        for file in &self.c_files {
+            println!("cargo:rerun-if-changed={}", file.display());
+            build.file(file);
+        }
+
+        println!("cargo:rerun-if-env-changed=FUTHARK_BINDGEN_CFLAGS");
+        if let Ok(flags) = std::env::var("FUTHARK_BINDGEN_CFLAGS") {
+            for flag in flags.split_whitespace() {
+                build.flag(flag);
+            }
+        }
+    }
+}
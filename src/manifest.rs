@@ -1,7 +1,8 @@
 use crate::*;
+use serde::Deserialize;
 
 /// Scalar types
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum ElemType {
     /// Signed 8 bit integer
     #[serde(rename = "i8")]
@@ -53,6 +54,11 @@ pub enum ElemType {
 }
 
 impl ElemType {
+    /// Parse the Futhark name of a scalar type
+    pub fn parse(s: &str) -> Option<ElemType> {
+        serde_json::from_value(serde_json::Value::String(s.to_string())).ok()
+    }
+
     pub fn to_str(&self) -> &'static str {
         match self {
             ElemType::I8 => "i8",
@@ -71,35 +77,127 @@ impl ElemType {
     }
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Output {
     pub r#type: String,
     pub unique: bool,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Input {
     pub name: String,
     pub r#type: String,
     pub unique: bool,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Entry {
     pub cfun: String,
     pub outputs: Vec<Output>,
     pub inputs: Vec<Input>,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+impl Entry {
+    /// Create an entry point without parameters or return values, calling the C function
+    /// `futhark_entry_{name}`
+    pub fn new(name: impl AsRef<str>) -> Entry {
+        Entry {
+            cfun: format!("futhark_entry_{}", name.as_ref()),
+            outputs: Vec::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Add a parameter
+    pub fn with_input(
+        mut self,
+        name: impl Into<String>,
+        ty: impl Into<String>,
+        unique: bool,
+    ) -> Self {
+        self.inputs.push(Input {
+            name: name.into(),
+            r#type: ty.into(),
+            unique,
+        });
+        self
+    }
+
+    /// Add a return value
+    pub fn with_output(mut self, ty: impl Into<String>, unique: bool) -> Self {
+        self.outputs.push(Output {
+            r#type: ty.into(),
+            unique,
+        });
+        self
+    }
+
+    /// Parameter names and types
+    pub fn inputs(&self) -> impl Iterator<Item = (&str, TypeRef<'_>)> {
+        self.inputs
+            .iter()
+            .map(|x| (x.name.as_str(), TypeRef::parse(&x.r#type)))
+    }
+
+    /// Return types
+    pub fn outputs(&self) -> impl Iterator<Item = TypeRef<'_>> {
+        self.outputs.iter().map(|x| TypeRef::parse(&x.r#type))
+    }
+}
+
+/// Type of an entry point parameter or return value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TypeRef<'a> {
+    /// Scalar value
+    Scalar(ElemType),
+
+    /// Array of scalars
+    Array {
+        /// Element type
+        elemtype: ElemType,
+
+        /// Number of dimensions
+        rank: usize,
+    },
+
+    /// Opaque type, the name can be looked up in `Manifest::types`
+    Opaque(&'a str),
+}
+
+impl<'a> TypeRef<'a> {
+    /// Classify a type name as it appears in the manifest
+    pub fn parse(name: &'a str) -> TypeRef<'a> {
+        let mut rank = 0;
+        let mut elem = name;
+        while let Some(x) = elem.strip_prefix("[]") {
+            rank += 1;
+            elem = x;
+        }
+        match ElemType::parse(elem) {
+            Some(elemtype) if rank == 0 => TypeRef::Scalar(elemtype),
+            Some(elemtype) => TypeRef::Array { elemtype, rank },
+            None => TypeRef::Opaque(name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ArrayOps {
     pub free: String,
     pub shape: String,
     pub values: String,
     pub new: String,
+
+    /// Returns the backend's raw pointer to the data, only listed by newer compilers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub values_raw: Option<String>,
+
+    /// Creates an array from a raw pointer of the backend, only listed by newer compilers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_raw: Option<String>,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ArrayType {
     pub ctype: String,
     pub rank: i32,
@@ -107,35 +205,100 @@ pub struct ArrayType {
     pub ops: ArrayOps,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct OpaqueOps {
     pub free: String,
     pub store: String,
     pub restore: String,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Field {
     pub name: String,
     pub project: String,
     pub r#type: String,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Record {
     pub new: String,
     pub fields: Vec<Field>,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Variant {
+    pub name: String,
+    pub construct: String,
+    pub destruct: String,
+    pub payload: Vec<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Sum {
+    pub variant: String,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct OpaqueArray {
+    pub rank: i32,
+    pub elemtype: String,
+    pub index: String,
+    pub shape: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RecordArray {
+    #[serde(flatten)]
+    pub array: OpaqueArray,
+    pub zip: String,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct OpaqueType {
     pub ctype: String,
     pub ops: OpaqueOps,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub record: Option<Record>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sum: Option<Sum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opaque_array: Option<OpaqueArray>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_array: Option<RecordArray>,
+}
+
+impl OpaqueType {
+    /// Types of the record fields, variant payloads or array elements
+    pub fn dependencies(&self) -> Vec<&str> {
+        let mut deps: Vec<&str> = Vec::new();
+        if let Some(record) = &self.record {
+            deps.extend(record.fields.iter().map(|x| x.r#type.as_str()));
+        }
+        if let Some(sum) = &self.sum {
+            deps.extend(
+                sum.variants
+                    .iter()
+                    .flat_map(|x| x.payload.iter().map(String::as_str)),
+            );
+        }
+        if let Some(array) = &self.opaque_array {
+            deps.push(&array.elemtype);
+        }
+        if let Some(array) = &self.record_array {
+            deps.push(&array.array.elemtype);
+            deps.extend(array.fields.iter().map(|x| x.r#type.as_str()));
+        }
+        deps
+    }
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "kind")]
+#[allow(clippy::large_enum_variant)]
 pub enum Type {
     #[serde(rename = "array")]
     Array(ArrayType),
@@ -143,20 +306,611 @@ pub enum Type {
     Opaque(OpaqueType),
 }
 
+/// Tuning parameter of the compiled program
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(from = "RawTuningParam")]
+pub struct TuningParam {
+    /// Kind of parameter, e.g. `threshold` or `group_size`
+    pub class: String,
+
+    /// Default value, if the manifest contains one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<usize>,
+}
+
+/// Tuning parameters are either listed with just their class or as an object
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum RawTuningParam {
+    Class(String),
+    Object {
+        class: String,
+        #[serde(default)]
+        default: Option<usize>,
+    },
+}
+
+impl From<RawTuningParam> for TuningParam {
+    fn from(raw: RawTuningParam) -> TuningParam {
+        match raw {
+            RawTuningParam::Class(class) => TuningParam {
+                class,
+                default: None,
+            },
+            RawTuningParam::Object { class, default } => TuningParam { class, default },
+        }
+    }
+}
+
 /// A Rust encoding of the Futhark manifest file
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Manifest {
     pub backend: Backend,
     pub version: String,
     pub entry_points: BTreeMap<String, Entry>,
     pub types: BTreeMap<String, Type>,
+    #[serde(default)]
+    pub tuning_params: BTreeMap<String, TuningParam>,
 }
 
 impl Manifest {
+    /// Create an empty manifest, the version is set to `Version::MINIMUM`
+    ///
+    /// ```
+    /// use futhark_bindgen::manifest::{ElemType, Entry, Type};
+    /// use futhark_bindgen::{Backend, Manifest};
+    ///
+    /// let manifest = Manifest::new(Backend::C)
+    ///     .with_array_type(ElemType::F32, 1)
+    ///     .with_entry_point("sum", Entry::new("sum").with_input("xs", "[]f32", false).with_output("f32", false));
+    ///
+    /// let json = serde_json::to_string(&manifest).unwrap();
+    /// let parsed = Manifest::parse_str(&json).unwrap();
+    /// assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    /// assert!(matches!(parsed.types["[]f32"], Type::Array(_)));
+    /// ```
+    pub fn new(backend: Backend) -> Manifest {
+        Manifest {
+            backend,
+            version: Version::MINIMUM.to_string(),
+            entry_points: BTreeMap::new(),
+            types: BTreeMap::new(),
+            tuning_params: BTreeMap::new(),
+        }
+    }
+
+    /// Set the compiler version
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    /// Add an entry point
+    pub fn with_entry_point(mut self, name: impl Into<String>, entry: Entry) -> Self {
+        self.entry_points.insert(name.into(), entry);
+        self
+    }
+
+    /// Add a type
+    pub fn with_type(mut self, name: impl Into<String>, ty: Type) -> Self {
+        self.types.insert(name.into(), ty);
+        self
+    }
+
+    /// Add an array type using the names the Futhark compiler generates, e.g. `[][]f32`
+    /// backed by `struct futhark_f32_2d`
+    pub fn with_array_type(self, elemtype: ElemType, rank: i32) -> Self {
+        let elem = elemtype.to_str();
+        let suffix = format!("{elem}_{rank}d");
+        let name = format!("{}{elem}", "[]".repeat(rank as usize));
+        self.with_type(
+            name,
+            Type::Array(ArrayType {
+                ctype: format!("struct futhark_{suffix} *"),
+                rank,
+                elemtype,
+                ops: ArrayOps {
+                    free: format!("futhark_free_{suffix}"),
+                    shape: format!("futhark_shape_{suffix}"),
+                    values: format!("futhark_values_{suffix}"),
+                    new: format!("futhark_new_{suffix}"),
+                    values_raw: Some(format!("futhark_values_raw_{suffix}")),
+                    new_raw: Some(format!("futhark_new_raw_{suffix}")),
+                },
+            }),
+        )
+    }
+
     /// Parse the manifest file
+    ///
+    /// Unknown fields are ignored, but the compiler version listed in the manifest has to be
+    /// in `Version::SUPPORTED_MANIFESTS`
     pub fn parse_file(filename: impl AsRef<std::path::Path>) -> Result<Manifest, Error> {
-        let r = std::fs::File::open(filename)?;
-        let manifest = serde_json::from_reader(r)?;
+        let filename = filename.as_ref();
+        let r = std::io::BufReader::new(std::fs::File::open(filename)?);
+        let value = serde_json::from_reader(r).map_err(|e| Error::InvalidManifest {
+            path: None,
+            context: None,
+            message: e.to_string(),
+        });
+        value.and_then(Manifest::from_value).map_err(|e| match e {
+            Error::InvalidManifest {
+                path: None,
+                context,
+                message,
+            } => Error::InvalidManifest {
+                path: Some(filename.to_path_buf()),
+                context,
+                message,
+            },
+            e => e,
+        })
+    }
+
+    /// Parse a manifest from a string, see `Manifest::parse_file`
+    ///
+    /// ```
+    /// use futhark_bindgen::manifest::{ElemType, TypeRef};
+    ///
+    /// let manifest = futhark_bindgen::Manifest::parse_str(r#"{
+    ///     "backend": "c",
+    ///     "version": "0.25.2",
+    ///     "entry_points": {
+    ///         "sum": {
+    ///             "cfun": "futhark_entry_sum",
+    ///             "inputs": [{"name": "xs", "type": "[]f32", "unique": false}],
+    ///             "outputs": [{"type": "f32", "unique": false}]
+    ///         }
+    ///     },
+    ///     "types": {}
+    /// }"#).unwrap();
+    ///
+    /// for (name, entry) in manifest.entry_points() {
+    ///     assert_eq!(name, "sum");
+    ///     let inputs: Vec<_> = entry.inputs().collect();
+    ///     let array = TypeRef::Array { elemtype: ElemType::F32, rank: 1 };
+    ///     assert_eq!(inputs, [("xs", array)]);
+    ///     let outputs: Vec<_> = entry.outputs().collect();
+    ///     assert_eq!(outputs, [TypeRef::Scalar(ElemType::F32)]);
+    /// }
+    /// ```
+    pub fn parse_str(s: &str) -> Result<Manifest, Error> {
+        let value = serde_json::from_str(s).map_err(|e| Error::InvalidManifest {
+            path: None,
+            context: None,
+            message: e.to_string(),
+        })?;
+        Manifest::from_value(value)
+    }
+
+    fn from_value(value: serde_json::Value) -> Result<Manifest, Error> {
+        // Check the version first, a manifest from an unsupported compiler is likely to fail
+        // to decode with a less helpful error
+        if let Some(s) = value.get("version").and_then(|v| v.as_str()) {
+            let found = Version::parse(s).ok_or_else(|| Error::InvalidVersion(s.to_string()))?;
+            if !Version::SUPPORTED_MANIFESTS.contains(&found) {
+                return Err(Error::UnsupportedManifestVersion {
+                    found,
+                    supported: Version::SUPPORTED_MANIFESTS,
+                });
+            }
+        }
+
+        let manifest: Manifest = match Manifest::deserialize(&value) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let (context, e) = match locate_error(&value) {
+                    Some((context, e)) => (Some(context), e),
+                    None => (None, e),
+                };
+                return Err(Error::InvalidManifest {
+                    path: None,
+                    context,
+                    message: e.to_string(),
+                });
+            }
+        };
+
+        // Parameter types are only names, make sure they refer to something
+        for (name, entry) in &manifest.entry_points {
+            let inputs = entry.inputs.iter().map(|x| ("input", &x.r#type));
+            let outputs = entry.outputs.iter().map(|x| ("output", &x.r#type));
+            let mut index = BTreeMap::new();
+            for (kind, t) in inputs.chain(outputs) {
+                let i = index.entry(kind).or_insert(0);
+                if let TypeRef::Opaque(t) = TypeRef::parse(t) {
+                    if !manifest.types.contains_key(t) {
+                        return Err(Error::InvalidManifest {
+                            path: None,
+                            context: Some(format!("entry point `{name}`, {kind} {i}")),
+                            message: format!("unknown type `{t}`"),
+                        });
+                    }
+                }
+                *i += 1;
+            }
+        }
         Ok(manifest)
     }
+
+    /// Backend the program was compiled with
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Entry points, sorted by name
+    pub fn entry_points(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.entry_points.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Array and opaque types, sorted by name
+    pub fn types(&self) -> impl Iterator<Item = (&str, &Type)> {
+        self.types.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Type names ordered so that the types an opaque type is built from come before it
+    pub fn types_in_dependency_order(&self) -> Vec<&str> {
+        fn visit<'a>(manifest: &'a Manifest, name: &'a str, out: &mut Vec<&'a str>) {
+            let ty = match manifest.types.get(name) {
+                Some(ty) if !out.contains(&name) => ty,
+                _ => return,
+            };
+            if let Type::Opaque(ty) = ty {
+                for dep in ty.dependencies() {
+                    visit(manifest, dep, out);
+                }
+            }
+            out.push(name);
+        }
+
+        let mut out = Vec::with_capacity(self.types.len());
+        for name in self.types.keys() {
+            visit(self, name, &mut out);
+        }
+        out
+    }
+
+    /// Keep only the entry points matching `f` and the types they use
+    pub fn filter_entry_points(&self, f: impl Fn(&str) -> bool) -> Manifest {
+        let entry_points: BTreeMap<_, _> = self
+            .entry_points
+            .iter()
+            .filter(|(name, _)| f(name))
+            .map(|(name, entry)| (name.clone(), entry.clone()))
+            .collect();
+
+        let mut queue: Vec<&str> = entry_points
+            .values()
+            .flat_map(|entry| {
+                let inputs = entry.inputs.iter().map(|x| x.r#type.as_str());
+                let outputs = entry.outputs.iter().map(|x| x.r#type.as_str());
+                inputs.chain(outputs)
+            })
+            .collect();
+        let mut types = BTreeMap::new();
+        while let Some(name) = queue.pop() {
+            // Scalar types don't have an entry in `types`
+            let ty = match self.types.get(name) {
+                Some(ty) if !types.contains_key(name) => ty,
+                _ => continue,
+            };
+            if let Type::Opaque(ty) = ty {
+                queue.extend(ty.dependencies());
+            }
+            types.insert(name.to_string(), ty.clone());
+        }
+
+        Manifest {
+            backend: self.backend,
+            version: self.version.clone(),
+            entry_points,
+            types,
+            tuning_params: self.tuning_params.clone(),
+        }
+    }
+}
+
+/// Decode each entry point and type on its own to find the one that doesn't match
+fn locate_error(value: &serde_json::Value) -> Option<(String, serde_json::Error)> {
+    let section = |name| {
+        value
+            .get(name)
+            .and_then(|x| x.as_object())
+            .into_iter()
+            .flatten()
+    };
+
+    for (name, entry) in section("entry_points") {
+        let e = match Entry::deserialize(entry) {
+            Ok(_) => continue,
+            Err(e) => e,
+        };
+        let params = |key| {
+            entry
+                .get(key)
+                .and_then(|x| x.as_array())
+                .into_iter()
+                .flatten()
+        };
+        for (i, x) in params("inputs").enumerate() {
+            if let Err(e) = Input::deserialize(x) {
+                return Some((format!("entry point `{name}`, input {i}"), e));
+            }
+        }
+        for (i, x) in params("outputs").enumerate() {
+            if let Err(e) = Output::deserialize(x) {
+                return Some((format!("entry point `{name}`, output {i}"), e));
+            }
+        }
+        return Some((format!("entry point `{name}`"), e));
+    }
+
+    for (name, ty) in section("types") {
+        if let Err(e) = Type::deserialize(ty) {
+            return Some((format!("type `{name}`"), e));
+        }
+    }
+
+    for (name, param) in section("tuning_params") {
+        if let Err(e) = TuningParam::deserialize(param) {
+            return Some((format!("tuning parameter `{name}`"), e));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Manifests written by different compiler versions, trimmed down to a few types each
+    const FUTHARK_0_21: &str = r#"{
+        "backend": "c",
+        "entry_points": {
+            "init": {
+                "cfun": "futhark_entry_init",
+                "inputs": [{"name": "xs", "type": "[]f32", "unique": false}],
+                "outputs": [{"type": "state", "unique": false}]
+            },
+            "sum": {
+                "cfun": "futhark_entry_sum",
+                "inputs": [{"name": "s", "type": "state", "unique": true}],
+                "outputs": [{"type": "f32", "unique": false}]
+            }
+        },
+        "types": {
+            "[]f32": {
+                "ctype": "struct futhark_f32_1d *",
+                "elemtype": "f32",
+                "kind": "array",
+                "ops": {
+                    "free": "futhark_free_f32_1d",
+                    "new": "futhark_new_f32_1d",
+                    "shape": "futhark_shape_f32_1d",
+                    "values": "futhark_values_f32_1d"
+                },
+                "rank": 1
+            },
+            "state": {
+                "ctype": "struct futhark_opaque_state *",
+                "kind": "opaque",
+                "ops": {
+                    "free": "futhark_free_opaque_state",
+                    "restore": "futhark_restore_opaque_state",
+                    "store": "futhark_store_opaque_state"
+                }
+            }
+        },
+        "version": "0.21.15"
+    }"#;
+
+    const FUTHARK_0_24: &str = r#"{
+        "backend": "opencl",
+        "entry_points": {
+            "norm": {
+                "cfun": "futhark_entry_norm",
+                "inputs": [{"name": "p", "type": "point", "unique": false}],
+                "outputs": [{"type": "f32", "unique": false}]
+            }
+        },
+        "types": {
+            "point": {
+                "ctype": "struct futhark_opaque_point *",
+                "kind": "opaque",
+                "ops": {
+                    "free": "futhark_free_opaque_point",
+                    "restore": "futhark_restore_opaque_point",
+                    "store": "futhark_store_opaque_point"
+                },
+                "record": {
+                    "fields": [
+                        {"name": "x", "project": "futhark_project_opaque_point_x", "type": "f32"},
+                        {"name": "y", "project": "futhark_project_opaque_point_y", "type": "f32"}
+                    ],
+                    "new": "futhark_new_opaque_point"
+                }
+            }
+        },
+        "tuning_params": {"norm.segred_group_size_1": "group_size"},
+        "version": "0.24.3"
+    }"#;
+
+    const FUTHARK_0_25: &str = r#"{
+        "backend": "cuda",
+        "entry_points": {
+            "first": {
+                "cfun": "futhark_entry_first",
+                "inputs": [{"name": "xs", "type": "[][]i64", "unique": false}],
+                "outputs": [{"type": "opt", "unique": false}]
+            }
+        },
+        "types": {
+            "[][]i64": {
+                "ctype": "struct futhark_i64_2d *",
+                "elemtype": "i64",
+                "kind": "array",
+                "ops": {
+                    "free": "futhark_free_i64_2d",
+                    "new": "futhark_new_i64_2d",
+                    "new_raw": "futhark_new_raw_i64_2d",
+                    "shape": "futhark_shape_i64_2d",
+                    "values": "futhark_values_i64_2d",
+                    "values_raw": "futhark_values_raw_i64_2d"
+                },
+                "rank": 2
+            },
+            "opt": {
+                "ctype": "struct futhark_opaque_opt *",
+                "kind": "opaque",
+                "ops": {
+                    "free": "futhark_free_opaque_opt",
+                    "restore": "futhark_restore_opaque_opt",
+                    "store": "futhark_store_opaque_opt"
+                },
+                "sum": {
+                    "variant": "futhark_variant_opaque_opt",
+                    "variants": [
+                        {
+                            "construct": "futhark_new_opaque_opt_none",
+                            "destruct": "futhark_destruct_opaque_opt_none",
+                            "name": "none",
+                            "payload": []
+                        },
+                        {
+                            "construct": "futhark_new_opaque_opt_some",
+                            "destruct": "futhark_destruct_opaque_opt_some",
+                            "name": "some",
+                            "payload": ["i64"]
+                        }
+                    ]
+                }
+            }
+        },
+        "tuning_params": {
+            "first.segmap_tblock_size_1": {"class": "thread_block_size", "default": null},
+            "first.suff_outer_par_0": {"class": "threshold", "default": 32768}
+        },
+        "version": "0.25.24"
+    }"#;
+
+    #[test]
+    fn parse_versions() {
+        let m = Manifest::parse_str(FUTHARK_0_21).unwrap();
+        assert_eq!(m.backend(), Backend::C);
+        assert!(m.tuning_params.is_empty());
+        let inputs: Vec<_> = m.entry_points["sum"].inputs().collect();
+        assert_eq!(inputs, [("s", TypeRef::Opaque("state"))]);
+        assert!(matches!(&m.types["state"], Type::Opaque(t) if t.record.is_none()));
+        assert_eq!(m.types_in_dependency_order(), ["[]f32", "state"]);
+
+        let m = Manifest::parse_str(FUTHARK_0_24).unwrap();
+        assert_eq!(m.backend(), Backend::OpenCL);
+        assert_eq!(
+            m.tuning_params["norm.segred_group_size_1"].class,
+            "group_size"
+        );
+        assert_eq!(m.tuning_params["norm.segred_group_size_1"].default, None);
+        let Type::Opaque(point) = &m.types["point"] else {
+            panic!("point should be opaque");
+        };
+        let fields: Vec<_> = point.record.iter().flat_map(|r| &r.fields).collect();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(point.dependencies(), ["f32", "f32"]);
+
+        let m = Manifest::parse_str(FUTHARK_0_25).unwrap();
+        assert_eq!(m.backend(), Backend::CUDA);
+        assert_eq!(
+            m.tuning_params["first.suff_outer_par_0"].default,
+            Some(32768)
+        );
+        assert_eq!(m.tuning_params["first.segmap_tblock_size_1"].default, None);
+        let Type::Array(array) = &m.types["[][]i64"] else {
+            panic!("[][]i64 should be an array");
+        };
+        assert_eq!((array.elemtype, array.rank), (ElemType::I64, 2));
+        assert!(array.ops.new_raw.is_some() && array.ops.values_raw.is_some());
+        let Type::Opaque(opt) = &m.types["opt"] else {
+            panic!("opt should be opaque");
+        };
+        assert_eq!(opt.sum.as_ref().unwrap().variants.len(), 2);
+        assert_eq!(m.types_in_dependency_order(), ["[][]i64", "opt"]);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        for version in ["0.20.8", "1.0.0"] {
+            let src = FUTHARK_0_25.replace("0.25.24", version);
+            let err = Manifest::parse_str(&src).unwrap_err();
+            let expected = Version::parse(version).unwrap();
+            assert!(matches!(
+                err,
+                Error::UnsupportedManifestVersion { found, supported }
+                    if found == expected && supported == Version::SUPPORTED_MANIFESTS
+            ));
+        }
+
+        let src = FUTHARK_0_25.replace("0.25.24", "latest");
+        let err = Manifest::parse_str(&src).unwrap_err();
+        assert!(matches!(err, Error::InvalidVersion(v) if v == "latest"));
+    }
+
+    #[test]
+    fn broken_manifests() {
+        let cases = [
+            (
+                r#""inputs": [{"name": "xs", "type": "[]f32", "unique": false}]"#,
+                r#""inputs": [{"name": "xs", "unique": false}]"#,
+                "Unable to decode manifest, entry point `init`, input 0: missing field `type`",
+            ),
+            (
+                r#""outputs": [{"type": "f32", "unique": false}]"#,
+                r#""outputs": [{"type": "f32", "unique": 1}]"#,
+                "Unable to decode manifest, entry point `sum`, output 0: invalid type: integer \
+                `1`, expected a boolean",
+            ),
+            (
+                r#""cfun": "futhark_entry_sum","#,
+                "",
+                "Unable to decode manifest, entry point `sum`: missing field `cfun`",
+            ),
+            (
+                r#""elemtype": "f32""#,
+                r#""elemtype": "f31""#,
+                "Unable to decode manifest, type `[]f32`: unknown variant `f31`",
+            ),
+            (
+                r#""outputs": [{"type": "state", "unique": false}]"#,
+                r#""outputs": [{"type": "stat", "unique": false}]"#,
+                "Unable to decode manifest, entry point `init`, output 0: unknown type `stat`",
+            ),
+            (
+                r#""version""#,
+                r#""tuning_params": {"sum.group_size_0": 256}, "version""#,
+                "Unable to decode manifest, tuning parameter `sum.group_size_0`: data did not \
+                match any variant",
+            ),
+        ];
+        for (from, to, message) in cases {
+            assert!(FUTHARK_0_21.contains(from), "{from}");
+            let err = Manifest::parse_str(&FUTHARK_0_21.replacen(from, to, 1)).unwrap_err();
+            assert!(matches!(err, Error::InvalidManifest { .. }));
+            assert!(err.to_string().starts_with(message), "{err}");
+        }
+
+        let err = Manifest::parse_str("{").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidManifest {
+                path: None,
+                context: None,
+                ..
+            }
+        ));
+
+        let path = std::env::temp_dir().join("futhark-bindgen-broken-manifest.json");
+        std::fs::write(&path, FUTHARK_0_21.replace("\"cfun\"", "\"c\"")).unwrap();
+        let err = Manifest::parse_file(&path).unwrap_err();
+        assert!(matches!(&err, Error::InvalidManifest { path: Some(p), .. } if *p == path));
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -9,20 +9,399 @@ pub struct Package {
     /// Path to the generated C file
     pub c_file: std::path::PathBuf,
 
-    /// Path to the generate C header file
+    /// Path to the generated C header file, this can be included by hand-written C code
     pub h_file: std::path::PathBuf,
 
     /// Source file
     pub src: std::path::PathBuf,
+
+    /// ISPC executable, used to compile the kernels when the package was
+    /// compiled with `Backend::ISPC`
+    pub ispc_exe: std::path::PathBuf,
+
+    /// Prefix added to all exported C symbols, see `Package::with_symbol_prefix`
+    pub symbol_prefix: Option<String>,
+
+    /// Print the C compiler command when linking
+    pub verbose: bool,
+
+    /// Name of the static library created when linking, see `Package::with_lib_name`
+    pub lib_name: Option<String>,
+
+    /// Options used when compiling the generated C code
+    pub link_options: LinkOptions,
+
+    /// Tuning file created by `futhark autotune`, see `Compiler::with_autotune`
+    pub tuning_file: Option<std::path::PathBuf>,
+
+    /// Output of `futhark --version` for the compiler that created the package, `None` when
+    /// loaded with `from_manifest`
+    pub compiler_version: Option<String>,
+}
+
+/// Find the paths referenced by `import` declarations in Futhark source code, skipping
+/// comments and string and character literals
+fn scan_imports(src: &str) -> Vec<&str> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_' || c == '\'';
+    let mut imports = Vec::new();
+    let mut i = 0;
+    while let Some(c) = src[i..].chars().next() {
+        let rest = &src[i..];
+        let word_start = !src[..i].ends_with(is_ident);
+        i += if rest.starts_with("--") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if c == '"' {
+            literal_len(rest, '"')
+        } else if c == '\'' && word_start {
+            literal_len(rest, '\'')
+        } else if let Some(after) = rest
+            .strip_prefix("import")
+            .filter(|after| word_start && !after.starts_with(is_ident))
+        {
+            let path = after.trim_start();
+            match path
+                .strip_prefix('"')
+                .and_then(|s| Some(&s[..s.find('"')?]))
+            {
+                Some(import) => {
+                    imports.push(import);
+                    rest.len() - path.len() + import.len() + 2
+                }
+                None => "import".len(),
+            }
+        } else {
+            c.len_utf8()
+        };
+    }
+    imports
+}
+
+/// Length of the string or character literal at the start of `s`, up to the closing quote
+/// or the end of the line
+fn literal_len(s: &str, quote: char) -> usize {
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' => return i,
+            c if c == quote => return i + 1,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+/// Get the directory containing `src`
+pub(crate) fn source_dir(src: &std::path::Path) -> &std::path::Path {
+    match src.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => std::path::Path::new("."),
+    }
+}
+
+/// Get `src` and every file it transitively imports
+pub(crate) fn source_files(src: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut files = Vec::new();
+    let mut queue = vec![src.to_path_buf()];
+    let mut seen = std::collections::BTreeSet::new();
+    while let Some(file) = queue.pop() {
+        if !seen.insert(file.canonicalize().unwrap_or_else(|_| file.clone())) {
+            continue;
+        }
+        files.push(file.clone());
+        let src = match std::fs::read_to_string(&file) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        let dir = file.parent().unwrap_or_else(|| std::path::Path::new(""));
+        for import in scan_imports(&src) {
+            // Skip the builtin prelude
+            if import.starts_with('/') {
+                continue;
+            }
+            let path = dir.join(format!("{import}.fut"));
+            if path.exists() {
+                queue.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// Runtime libraries of a backend found using `pkg-config`
+#[cfg(feature = "build")]
+#[derive(Default)]
+struct PkgConfig {
+    /// Packages that were found, these replace the default library names
+    packages: &'static [&'static str],
+    link_search: Vec<std::path::PathBuf>,
+    libs: Vec<String>,
+    include: Vec<std::path::PathBuf>,
+}
+
+/// Query `pkg-config` for the runtime libraries of `backend`
+#[cfg(feature = "pkg-config")]
+fn pkg_config(backend: Backend, target: &str) -> Option<PkgConfig> {
+    let packages: &[&str] = match backend {
+        Backend::OpenCL => &["OpenCL"],
+        Backend::CUDA => &["cuda", "cudart", "nvrtc"],
+        _ => return None,
+    };
+
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG");
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+    let fallback = |reason: &str| {
+        warn(&format!(
+            "{reason}, using the default library names for {}",
+            packages.join(", ")
+        ));
+        None
+    };
+
+    // The host `.pc` files describe the wrong libraries when cross-compiling
+    if std::env::var("HOST").is_ok_and(|host| host != target) {
+        return fallback("pkg-config is not used when cross-compiling");
+    }
+
+    let exe = std::env::var_os("PKG_CONFIG").unwrap_or_else(|| "pkg-config".into());
+    let out = match std::process::Command::new(exe)
+        .args(["--libs", "--cflags"])
+        .args(packages)
+        .output()
+    {
+        Ok(out) if out.status.success() => out,
+        Ok(_) => return fallback("pkg-config could not find all packages"),
+        Err(_) => return fallback("pkg-config not found"),
+    };
+
+    let mut found = PkgConfig {
+        packages,
+        ..PkgConfig::default()
+    };
+    for arg in String::from_utf8_lossy(&out.stdout).split_whitespace() {
+        if let Some(dir) = arg.strip_prefix("-L") {
+            found.link_search.push(dir.into());
+        } else if let Some(lib) = arg.strip_prefix("-l") {
+            found.libs.push(lib.to_string());
+        } else if let Some(dir) = arg.strip_prefix("-I") {
+            found.include.push(dir.into());
+        }
+    }
+    Some(found)
+}
+
+#[cfg(all(feature = "build", not(feature = "pkg-config")))]
+fn pkg_config(_backend: Backend, _target: &str) -> Option<PkgConfig> {
+    None
+}
+
+/// Find the library and include directories of the SDK required by `backend`
+///
+/// The library directory can be set explicitly using `FUTHARK_BINDGEN_CUDA_LIB_DIR` or
+/// `FUTHARK_BINDGEN_OPENCL_LIB_DIR`, otherwise it is found relative to the SDK root
+/// given by `CUDA_PATH`, `CUDA_HOME` or `OPENCL_ROOT`
+fn sdk_paths(
+    backend: Backend,
+    target: &str,
+) -> (Option<std::path::PathBuf>, Option<std::path::PathBuf>) {
+    let (lib_var, root_vars, default_root): (_, &[_], _) = match backend {
+        Backend::CUDA => (
+            "FUTHARK_BINDGEN_CUDA_LIB_DIR",
+            &["CUDA_PATH", "CUDA_HOME"],
+            Some("/usr/local/cuda"),
+        ),
+        Backend::OpenCL => ("FUTHARK_BINDGEN_OPENCL_LIB_DIR", &["OPENCL_ROOT"], None),
+        _ => return (None, None),
+    };
+
+    println!("cargo:rerun-if-env-changed={lib_var}");
+    for var in root_vars {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+
+    let root = root_vars
+        .iter()
+        .find_map(std::env::var_os)
+        .map(std::path::PathBuf::from)
+        .or_else(|| {
+            default_root
+                .map(std::path::PathBuf::from)
+                .filter(|p| p.is_dir())
+        });
+
+    // CUDA installations support cross-compilation to ARM using a separate directory
+    let arm = backend == Backend::CUDA && target.starts_with("aarch64") && target.contains("linux");
+    let lib = match std::env::var_os(lib_var) {
+        Some(dir) => Some(std::path::PathBuf::from(dir)),
+        None => root.as_ref().and_then(|root| {
+            let dirs: &[&str] = if target.contains("windows") {
+                &["lib/x64", "lib"]
+            } else if arm {
+                &[
+                    "targets/aarch64-linux/lib",
+                    "targets/sbsa-linux/lib",
+                    "lib64",
+                    "lib",
+                ]
+            } else {
+                &["lib64", "lib"]
+            };
+            dirs.iter().map(|d| root.join(d)).find(|p| p.is_dir())
+        }),
+    };
+    let include = root.and_then(|root| {
+        let dirs: &[&str] = if arm {
+            &["targets/aarch64-linux/include", "include"]
+        } else {
+            &["include"]
+        };
+        dirs.iter().map(|d| root.join(d)).find(|p| p.is_dir())
+    });
+    (lib, include)
+}
+
+/// Check whether the SDK required by `backend` can be found, the Futhark compiler only
+/// generates C code so a missing SDK isn't noticed until the C code is compiled
+///
+/// A library directory set explicitly has to exist, otherwise the directories found by
+/// `sdk_paths` and the system include directories are searched
+pub(crate) fn sdk_available(backend: Backend, target: &str) -> bool {
+    let (lib_var, header) = match backend {
+        Backend::CUDA => (Some("FUTHARK_BINDGEN_CUDA_LIB_DIR"), "cuda.h"),
+        Backend::OpenCL if target.contains("apple") => return true,
+        Backend::OpenCL => (Some("FUTHARK_BINDGEN_OPENCL_LIB_DIR"), "CL/cl.h"),
+        Backend::HIP => (None, "hip/hip_runtime.h"),
+        _ => return true,
+    };
+
+    let (lib, include) = sdk_paths(backend, target);
+    if lib_var.is_some_and(|var| std::env::var_os(var).is_some()) {
+        return lib.is_some_and(|dir| dir.is_dir());
+    }
+    if lib.is_some() || include.is_some() {
+        return true;
+    }
+    ["/usr/include", "/usr/local/include", "/opt/rocm/include"]
+        .iter()
+        .any(|dir| std::path::Path::new(dir).join(header).is_file())
 }
 
 impl Package {
+    /// Load the outputs of a previous compilation from its manifest, the C file and
+    /// header are expected to be next to the manifest
+    ///
+    /// Only the manifest is needed to generate bindings and to link a prebuilt library
+    /// using `link_prebuilt`. A tuning file next to the manifest is used if it exists
+    pub fn from_manifest(path: impl AsRef<std::path::Path>) -> Result<Package, Error> {
+        let path = path.as_ref();
+        let tuning_file = path.with_extension("tuning");
+        Ok(Package {
+            manifest: Manifest::parse_file(path)?,
+            c_file: path.with_extension("c"),
+            h_file: path.with_extension("h"),
+            src: path.with_extension("fut"),
+            ispc_exe: std::path::PathBuf::from("ispc"),
+            symbol_prefix: None,
+            verbose: false,
+            lib_name: None,
+            link_options: LinkOptions::default(),
+            tuning_file: tuning_file.exists().then_some(tuning_file),
+            compiler_version: None,
+        })
+    }
+
+    /// Prefix all exported C symbols with `prefix`, this allows multiple Futhark
+    /// programs to be linked into the same binary
+    ///
+    /// The prefix is applied when linking and when generating Rust code, every
+    /// function declared in the generated C header is renamed with `-include`,
+    /// which requires a GCC compatible C compiler
+    pub fn with_symbol_prefix(mut self, prefix: impl AsRef<str>) -> Self {
+        self.symbol_prefix = Some(prefix.as_ref().to_string());
+        self
+    }
+
+    /// Set the name of the static library created when linking
+    ///
+    /// The default is `futhark_{project}_{stem}`, where `project` is `CARGO_PKG_NAME` and
+    /// `stem` is the file name of the generated C code without extension
+    pub fn with_lib_name(mut self, name: impl AsRef<str>) -> Self {
+        self.lib_name = Some(name.as_ref().to_string());
+        self
+    }
+
+    /// Set the options used when compiling the generated C code
+    pub fn with_link_options(mut self, options: LinkOptions) -> Self {
+        self.link_options = options;
+        self
+    }
+
+    /// Get the name of the static library created when linking, this can be used to
+    /// emit `cargo:rustc-link-lib` directives manually
+    ///
+    /// Note: This should only be used in `build.rs`, unless a name was set using
+    /// `with_lib_name`
+    pub fn lib_name(&self) -> Result<String, Error> {
+        if let Some(name) = &self.lib_name {
+            return Ok(name.clone());
+        }
+
+        // Package names may contain `-`, which isn't accepted in library names by every archiver
+        let sanitize = |s: &str| s.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        let project = std::env::var("CARGO_PKG_NAME")
+            .map_err(|_| Error::MissingEnvVar("CARGO_PKG_NAME".to_string()))?;
+        let stem = self
+            .c_file
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        Ok(format!(
+            "futhark_{}_{}",
+            sanitize(&project),
+            sanitize(&stem)
+        ))
+    }
+
+    /// Get the source file and every file it transitively imports, including
+    /// packages fetched into `lib/` by `futhark pkg`
+    pub fn source_files(&self) -> Vec<std::path::PathBuf> {
+        source_files(&self.src)
+    }
+
+    /// Write a header that renames every function declared in the generated C header
     #[cfg(feature = "build")]
-    fn build(&self, libname: &str) {
-        if self.manifest.backend == Backend::ISPC {
+    fn write_prefix_header(&self, prefix: &str) -> Result<std::path::PathBuf, Error> {
+        let header = std::fs::read_to_string(&self.h_file)?;
+        let mut names = std::collections::BTreeSet::new();
+        let mut rest = header.as_str();
+        while let Some(i) = rest.find("futhark_") {
+            let start = &rest[i..];
+            let len = start
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(start.len());
+            if start[len..].trim_start().starts_with('(') {
+                names.insert(&start[..len]);
+            }
+            rest = &start[len..];
+        }
+
+        let path = self.c_file.with_extension("prefix.h");
+        let defines: String = names
+            .iter()
+            .map(|name| format!("#define {name} {prefix}{name}\n"))
+            .collect();
+        std::fs::write(&path, defines)?;
+        Ok(path)
+    }
+
+    #[cfg(feature = "build")]
+    fn build(&self, libname: &str, f: impl FnOnce(&mut cc::Build)) -> Result<(), Error> {
+        let mut build = if self.manifest.backend == Backend::ISPC {
             let kernels = self.c_file.with_extension("kernels.ispc");
             let dest = kernels.with_extension("o");
-            std::process::Command::new("ispc")
+            let ok = std::process::Command::new(&self.ispc_exe)
                 .arg(&kernels)
                 .arg("-o")
                 .arg(&dest)
@@ -30,51 +409,321 @@ impl Package {
                 .arg("--addressing=64")
                 .arg("--target=host")
                 .arg("-O3")
-                .status()
-                .expect("Unable to run ispc");
+                .status()?
+                .success();
+            if !ok {
+                return Err(Error::LinkFailed(format!(
+                    "ispc failed to compile {}",
+                    kernels.display()
+                )));
+            }
 
-            cc::Build::new()
+            let mut build = cc::Build::new();
+            build
                 .file(&self.c_file)
                 .object(&dest)
-                .flag("-fPIC")
-                .flag("-pthread")
-                .flag("-lm")
-                .flag("-std=c99")
-                .flag("-O3")
+                .flag_if_supported("-fPIC")
+                .flag_if_supported("-pthread")
+                .flag_if_supported("-std=c99")
                 .extra_warnings(false)
-                .warnings(false)
-                .compile(libname);
+                .warnings(false);
+            build
         } else {
-            cc::Build::new()
-                .flag("-std=c99")
-                .flag("-Wno-unused-parameter")
-                .flag("-O3")
+            let mut build = cc::Build::new();
+            build
+                .flag_if_supported("-std=c99")
+                .flag_if_supported("-Wno-unused-parameter")
                 .file(&self.c_file)
                 .extra_warnings(false)
-                .warnings(false)
-                .compile(libname);
+                .warnings(false);
+            build
+        };
+
+        if let Some(prefix) = &self.symbol_prefix {
+            let header = self.write_prefix_header(prefix)?;
+            let msvc = build
+                .try_get_compiler()
+                .map(|c| c.is_like_msvc())
+                .unwrap_or(false);
+            if msvc {
+                let mut flag = std::ffi::OsString::from("/FI");
+                flag.push(header.as_os_str());
+                build.flag(flag);
+            } else {
+                build.flag("-include").flag(header.as_os_str());
+            }
+        }
+
+        if let Some(dir) = self.h_file.parent() {
+            build.include(dir);
         }
+        self.link_options.apply(&mut build);
+        f(&mut build);
+
+        if self.verbose {
+            if let Ok(compiler) = build.try_get_compiler() {
+                warn(&format!(
+                    "compiling {} with {:?}",
+                    self.c_file.display(),
+                    compiler.to_command()
+                ));
+            }
+        }
+
+        build
+            .try_compile(libname)
+            .map_err(|e| Error::LinkFailed(e.to_string()))
     }
+
     /// Link the package
     ///
     /// Note: This should only be used in `build.rs`
     #[cfg(feature = "build")]
     pub fn link(&self) {
-        let project = std::env::var("CARGO_PKG_NAME").unwrap();
-        let name = format!("futhark_generate_{project}");
-        self.build(&name);
+        self.try_link().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Link the package, returning an error instead of panicking on failure
+    ///
+    /// Note: This should only be used in `build.rs`
+    #[cfg(feature = "build")]
+    pub fn try_link(&self) -> Result<(), Error> {
+        self.try_link_with(|_| ())
+    }
 
-        println!("cargo:rerun-if-changed={}", self.src.display());
+    /// Link the package, `f` can be used to customize the `cc::Build` used to compile
+    /// the generated C code, it is called after the default flags have been set
+    ///
+    /// Note: This should only be used in `build.rs`
+    #[cfg(feature = "build")]
+    pub fn link_with(&self, f: impl FnOnce(&mut cc::Build)) {
+        self.try_link_with(f).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Link the package into a static library called `name`, see `with_lib_name`
+    ///
+    /// Note: This should only be used in `build.rs`
+    #[cfg(feature = "build")]
+    pub fn link_named(&self, name: &str) {
+        self.clone().with_lib_name(name).link()
+    }
+
+    /// Same as `link_with`, but returns an error instead of panicking on failure
+    ///
+    /// Note: This should only be used in `build.rs`
+    #[cfg(feature = "build")]
+    pub fn try_link_with(&self, f: impl FnOnce(&mut cc::Build)) -> Result<(), Error> {
+        let name = self.lib_name()?;
+        let target = std::env::var("TARGET").unwrap_or_default();
+        let (lib_dir, include_dir) = sdk_paths(self.manifest.backend, &target);
+        let pkg = self.pkg_config(&target);
+        self.build(&name, |build| {
+            build.includes(&pkg.include);
+            if let Some(dir) = &include_dir {
+                build.include(dir);
+            }
+            f(build)
+        })?;
+
+        for file in self.source_files() {
+            println!("cargo:rerun-if-changed={}", file.display());
+        }
+
+        let dir = source_dir(&self.src);
+        if dir.join("futhark.pkg").exists() {
+            println!(
+                "cargo:rerun-if-changed={}",
+                dir.join("futhark.pkg").display()
+            );
+            if dir.join("lib").exists() {
+                println!("cargo:rerun-if-changed={}", dir.join("lib").display());
+            }
+        }
         println!("cargo:rustc-link-lib={name}");
+        self.link_required_libs(&target, lib_dir.as_deref(), &pkg);
+        self.export_metadata();
+        Ok(())
+    }
+
+    /// When the crate sets `links` in its `Cargo.toml`, pass the include directory and manifest
+    /// to the build scripts of dependent crates as `DEP_{LINKS}_INCLUDE` and
+    /// `DEP_{LINKS}_MANIFEST_JSON`
+    #[cfg(feature = "build")]
+    fn export_metadata(&self) {
+        if std::env::var_os("CARGO_MANIFEST_LINKS").is_none() {
+            return;
+        }
+        if let Some(dir) = self.h_file.parent() {
+            println!("cargo:include={}", dir.display());
+        }
+        println!(
+            "cargo:manifest_json={}",
+            self.c_file.with_extension("json").display()
+        );
+    }
 
-        let libs = self.manifest.backend.required_c_libs();
+    /// Link a static library containing the compiled C code, instead of compiling it,
+    /// for example one built on another machine from the same manifest
+    ///
+    /// Note: This should only be used in `build.rs`
+    #[cfg(feature = "build")]
+    pub fn link_prebuilt(&self, archive: impl AsRef<std::path::Path>) {
+        self.try_link_prebuilt(archive)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
 
-        for lib in libs {
-            if cfg!(target_os = "macos") && lib == &"OpenCL" {
-                println!("cargo:rustc-link-lib=framework={}", lib);
-            } else {
-                println!("cargo:rustc-link-lib={}", lib);
+    /// Same as `link_prebuilt`, but returns an error instead of panicking on failure
+    ///
+    /// Note: This should only be used in `build.rs`
+    #[cfg(feature = "build")]
+    pub fn try_link_prebuilt(&self, archive: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        let archive = archive.as_ref();
+        if !archive.is_file() {
+            return Err(Error::LinkFailed(format!(
+                "prebuilt library not found: {}",
+                archive.display()
+            )));
+        }
+        let file_name = archive.file_name().unwrap_or_default().to_string_lossy();
+        let name = file_name
+            .strip_suffix(".a")
+            .or_else(|| file_name.strip_suffix(".lib"))
+            .ok_or_else(|| {
+                Error::LinkFailed(format!(
+                    "expected a static library ending in .a or .lib: {}",
+                    archive.display()
+                ))
+            })?;
+        let name = name.strip_prefix("lib").unwrap_or(name);
+        let dir = archive
+            .canonicalize()?
+            .parent()
+            .map(std::path::Path::to_path_buf)
+            .unwrap_or_default();
+
+        println!("cargo:rerun-if-changed={}", archive.display());
+        println!("cargo:rustc-link-search=native={}", dir.display());
+        println!("cargo:rustc-link-lib=static={name}");
+
+        let target = std::env::var("TARGET").unwrap_or_default();
+        let (lib_dir, _) = sdk_paths(self.manifest.backend, &target);
+        let pkg = self.pkg_config(&target);
+        self.link_required_libs(&target, lib_dir.as_deref(), &pkg);
+        self.export_metadata();
+        Ok(())
+    }
+
+    /// Query `pkg-config` for the libraries needed by the backend
+    #[cfg(feature = "build")]
+    fn pkg_config(&self, target: &str) -> PkgConfig {
+        if !self.link_options.system_libs {
+            return PkgConfig::default();
+        }
+        pkg_config(self.manifest.backend, target).unwrap_or_default()
+    }
+
+    /// Link the libraries needed by the backend, using the ones found by `pkg-config`
+    /// where possible
+    #[cfg(feature = "build")]
+    fn link_required_libs(&self, target: &str, lib_dir: Option<&std::path::Path>, pkg: &PkgConfig) {
+        if !self.link_options.system_libs {
+            return;
+        }
+
+        for dir in self.link_options.link_search.iter().chain(&pkg.link_search) {
+            println!("cargo:rustc-link-search=native={}", dir.display());
+        }
+        if let Some(dir) = lib_dir {
+            println!("cargo:rustc-link-search=native={}", dir.display());
+        }
+
+        let static_cudart = self.manifest.backend == Backend::CUDA
+            && self.link_options.cuda_runtime == CudaRuntime::Static;
+        let link = |lib: RequiredLib| match lib {
+            RequiredLib::Lib("cudart") if static_cudart => {
+                println!("cargo:rustc-link-lib=static=cudart_static")
+            }
+            lib => println!("{}", lib.link_directive()),
+        };
+
+        for lib in &pkg.libs {
+            match lib.as_str() {
+                "cudart" => link(RequiredLib::Lib("cudart")),
+                _ => println!("cargo:rustc-link-lib={lib}"),
             }
         }
+        for lib in self.manifest.backend.required_libs(target) {
+            if !pkg.packages.contains(&lib.name()) {
+                link(lib);
+            }
+        }
+
+        // The static runtime depends on these, they need to come after it
+        if static_cudart && target.contains("linux") {
+            for lib in ["rt", "dl", "pthread"] {
+                println!("cargo:rustc-link-lib={lib}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports() {
+        let src = r#"
+-- import "commented"
+import "lib/a" -- import "trailing"
+import"../b"
+local open import "nested/c"
+def s = "import \"in_string\" -- not a comment" import "after_string"
+def c = '"' import "after_char"
+def x' = 1 import "after_prime"
+def importance = 1
+def my_import = importer "longer"
+"#;
+        assert_eq!(
+            scan_imports(src),
+            [
+                "lib/a",
+                "../b",
+                "nested/c",
+                "after_string",
+                "after_char",
+                "after_prime"
+            ]
+        );
+    }
+
+    #[test]
+    fn source_files_follow_imports() {
+        let dir =
+            std::env::temp_dir().join(format!("futhark-bindgen-imports-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let write = |name: &str, src: &str| std::fs::write(dir.join(name), src).unwrap();
+        write(
+            "main.fut",
+            "import \"lib/a\"\nimport \"/prelude/math\"\nimport \"missing\"\n",
+        );
+        write("lib/a.fut", "import \"../b\"\nimport \"c\"\n");
+        write("lib/c.fut", "-- import \"../unused\"\n");
+        // Imports the entry file again
+        write("b.fut", "import \"main\"\n");
+        write("unused.fut", "");
+
+        let files: std::collections::BTreeSet<_> = source_files(&dir.join("main.fut"))
+            .iter()
+            .map(|f| f.canonicalize().unwrap())
+            .collect();
+        let expected: std::collections::BTreeSet<_> =
+            ["main.fut", "lib/a.fut", "lib/c.fut", "b.fut"]
+                .iter()
+                .map(|f| dir.join(f).canonicalize().unwrap())
+                .collect();
+        assert_eq!(files, expected);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
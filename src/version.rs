@@ -0,0 +1,53 @@
+/// Futhark compiler version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// Major version
+    pub major: u32,
+
+    /// Minor version
+    pub minor: u32,
+
+    /// Patch version
+    pub patch: u32,
+}
+
+impl Version {
+    /// Oldest Futhark compiler known to produce manifests that can be used for code generation
+    pub const MINIMUM: Version = Version::new(0, 21, 0);
+
+    /// Compiler versions whose manifests can be parsed
+    pub const SUPPORTED_MANIFESTS: std::ops::Range<Version> =
+        Version::MINIMUM..Version::new(1, 0, 0);
+
+    /// Create a new `Version`
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parse the output of `futhark --version`, or a plain version string like `0.25.2`
+    pub fn parse(s: &str) -> Option<Version> {
+        let line = s.lines().next()?.trim();
+        let line = line.strip_prefix("Futhark").unwrap_or(line).trim();
+        let v = line.split_whitespace().next()?;
+
+        // Ignore any suffix, e.g. `0.26.0-prerelease`
+        let mut parts = v.split('.').map(|x| {
+            let n = x.find(|c: char| !c.is_ascii_digit()).unwrap_or(x.len());
+            x[..n].parse::<u32>().ok()
+        });
+        let major = parts.next()??;
+        let minor = parts.next()??;
+        let patch = parts.next().unwrap_or(Some(0))?;
+        Some(Version::new(major, minor, patch))
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}